@@ -0,0 +1,858 @@
+//! ADCs that measure sensor voltages. AnyLeaf boards use an ADS1115 over I2C; this
+//! also supports the ADS1015, ADS1113 and ADS1114, which share its register layout,
+//! and the MCP342x family. `OneShotAdc` reads a probe with an MCU's onboard ADC, via
+//! `embedded_hal::adc::OneShot`.
+
+use core::{cmp::Ordering, convert::Infallible, marker::PhantomData};
+
+use embedded_hal::{
+    adc::{Channel, OneShot},
+    blocking::i2c::{Read, Write, WriteRead},
+};
+
+#[allow(unused_imports)]
+use num_traits::float::Float; // Required for `sqrt` and `powi` in `no_std`.
+
+use crate::{AdcChannel, SensorError};
+
+// ADS1x15 registers.
+pub(crate) const CFG_REG: u8 = 0x1;
+pub(crate) const CONV_REG: u8 = 0x0;
+
+// See ads1115 datasheet Section 9.6.3: Config Register. Start a conversion in one-shot
+// mode, with no alert pin activity. MUX and PGA bits are set per reading.
+const ADS_CMD: u16 = 0b1000_0001_1000_0000;
+// The MCP342x's reference voltage.
+const MCP_VREF: f32 = 2.048;
+// Readings averaged when measuring ADC offset or gain.
+const ADC_CAL_SAMPLES: u8 = 8;
+/// The most conversions `Oversampling` combines into one reading.
+pub const MAX_SAMPLES: usize = 32;
+
+#[derive(Clone, Copy, Debug)]
+/// MCP342x resolution. Higher resolutions are slower; 18 bits takes ~270ms.
+pub enum McpResolution {
+    Bits12,
+    Bits14,
+    Bits16,
+    Bits18,
+}
+
+impl McpResolution {
+    fn bits(&self) -> u8 {
+        match self {
+            Self::Bits12 => 12,
+            Self::Bits14 => 14,
+            Self::Bits16 => 16,
+            Self::Bits18 => 18,
+        }
+    }
+
+    /// Config register S1:S0 bits. See MCP342x datasheet, Register 5-1.
+    fn sample_rate_bits(&self) -> u8 {
+        match self {
+            Self::Bits12 => 0b00,
+            Self::Bits14 => 0b01,
+            Self::Bits16 => 0b10,
+            Self::Bits18 => 0b11,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// The ADC part.
+pub enum AdcModel {
+    /// TI ADS1115: 16 bits, 4 inputs. Used on AnyLeaf boards.
+    Ads1115,
+    /// TI ADS1114: 16 bits, 1 differential input.
+    Ads1114,
+    /// TI ADS1113: 16 bits, 1 differential input, with a fixed ±2.048V range.
+    Ads1113,
+    /// TI ADS1015: 12 bits, 4 inputs.
+    Ads1015,
+    /// Microchip MCP3421 to MCP3424, with `channels` differential inputs: 1, 2 or 4.
+    /// These have a ±2.048V range, and gain of up to 8.
+    Mcp342x {
+        resolution: McpResolution,
+        channels: u8,
+    },
+}
+
+impl AdcModel {
+    /// Whether the part has `input`. Parts with a single input only have the probe.
+    fn has_input(&self, input: AdcInput) -> bool {
+        match (self, input) {
+            (_, AdcInput::Probe) => true,
+            (Self::Ads1114, _) | (Self::Ads1113, _) => false,
+            (Self::Mcp342x { channels, .. }, AdcInput::Single(ch)) => {
+                *channels > 1 && (ch as u8) < *channels
+            }
+            _ => true,
+        }
+    }
+
+    /// Whether the part can measure `range`, without clipping.
+    fn has_range(&self, range: FullScale) -> bool {
+        match self {
+            Self::Ads1113 | Self::Mcp342x { .. } => range.volts() <= MCP_VREF,
+            _ => true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// An ADC input to measure.
+pub enum AdcInput {
+    /// The probe: differential across A0 and A1 on the ADS1115, or channel 1 on the
+    /// MCP342x.
+    Probe,
+    /// A single-ended input. On the MCP342x, `A0` to `A3` are channels 1 to 4.
+    Single(AdcChannel),
+}
+
+#[derive(Clone, Copy, Debug)]
+/// Full scale range, ie ± this voltage.
+pub enum FullScale {
+    V6_144,
+    V4_096,
+    V2_048,
+    V1_024,
+    V0_512,
+    V0_256,
+}
+
+impl FullScale {
+    pub fn volts(&self) -> f32 {
+        match self {
+            Self::V6_144 => 6.144,
+            Self::V4_096 => 4.096,
+            Self::V2_048 => 2.048,
+            Self::V1_024 => 1.024,
+            Self::V0_512 => 0.512,
+            Self::V0_256 => 0.256,
+        }
+    }
+
+    /// ADS1x15 config register PGA bits.
+    fn ads_pga_bits(&self) -> u16 {
+        match self {
+            Self::V6_144 => 0b000,
+            Self::V4_096 => 0b001,
+            Self::V2_048 => 0b010,
+            Self::V1_024 => 0b011,
+            Self::V0_512 => 0b100,
+            Self::V0_256 => 0b101,
+        }
+    }
+
+    /// MCP342x gain, and its config register G1:G0 bits. Ranges above ±2.048V
+    /// use a gain of 1.
+    fn mcp_gain(&self) -> (f32, u8) {
+        match self {
+            Self::V6_144 | Self::V4_096 | Self::V2_048 => (1., 0b00),
+            Self::V1_024 => (2., 0b01),
+            Self::V0_512 => (4., 0b10),
+            Self::V0_256 => (8., 0b11),
+        }
+    }
+}
+
+/// Measures sensor voltages. Sensors are generic over this, so calibration, temperature
+/// compensation and filtering work with any ADC. `BUS` is what the ADC is read through;
+/// eg an I2C bus, or an MCU's ADC peripheral.
+pub trait VoltageSource<BUS> {
+    type Error;
+
+    /// Take a reading, in Volts.
+    fn read_voltage(
+        &mut self,
+        input: AdcInput,
+        range: FullScale,
+        bus: &mut BUS,
+    ) -> Result<f32, Self::Error>;
+}
+
+#[derive(Clone, Copy, Debug)]
+/// Corrects ADC offset and gain error: `V = (V_measured - offset) * gain`. At pH 7 the
+/// probe is near 0V, so a few hundred µV of offset shifts readings noticeably.
+pub struct AdcCal {
+    /// In V.
+    pub offset: f32,
+    pub gain: f32,
+}
+
+impl Default for AdcCal {
+    fn default() -> Self {
+        Self {
+            offset: 0.,
+            gain: 1.,
+        }
+    }
+}
+
+impl AdcCal {
+    pub fn new(offset: f32, gain: f32) -> Self {
+        Self { offset, gain }
+    }
+
+    /// Correct a reading, in V.
+    pub fn apply(&self, V: f32) -> f32 {
+        (V - self.offset) * self.gain
+    }
+
+    /// Measure offset, with `input` at 0V. Sets the offset, and returns it.
+    pub fn measure_offset<A, BUS>(
+        &mut self,
+        adc: &mut A,
+        input: AdcInput,
+        range: FullScale,
+        bus: &mut BUS,
+    ) -> Result<f32, A::Error>
+    where
+        A: VoltageSource<BUS>,
+    {
+        self.offset = read_mean(adc, input, range, bus)?;
+        Ok(self.offset)
+    }
+
+    /// Measure gain, with `input` at a known voltage `V_ref`, in V. Measure offset
+    /// first. Sets the gain, and returns it. Returns `AdcError::Gain`, leaving the gain
+    /// unchanged, if the input reads at the offset, eg when shorted, or `V_ref` is 0.
+    pub fn measure_gain<A, BUS>(
+        &mut self,
+        adc: &mut A,
+        input: AdcInput,
+        range: FullScale,
+        V_ref: f32,
+        bus: &mut BUS,
+    ) -> Result<f32, AdcError<A::Error>>
+    where
+        A: VoltageSource<BUS>,
+    {
+        let V = read_mean(adc, input, range, bus).map_err(AdcError::Bus)?;
+        let gain = V_ref / (V - self.offset);
+
+        if !gain.is_normal() {
+            return Err(AdcError::Gain);
+        }
+
+        self.gain = gain;
+        Ok(self.gain)
+    }
+}
+
+/// Average several readings, to reduce noise in calibration measurements.
+fn read_mean<A, BUS>(
+    adc: &mut A,
+    input: AdcInput,
+    range: FullScale,
+    bus: &mut BUS,
+) -> Result<f32, A::Error>
+where
+    A: VoltageSource<BUS>,
+{
+    let mut sum = 0.;
+    for _ in 0..ADC_CAL_SAMPLES {
+        sum += adc.read_voltage(input, range, bus)?;
+    }
+    Ok(sum / ADC_CAL_SAMPLES as f32)
+}
+
+#[derive(Clone, Copy, Debug)]
+/// How to combine conversions into one reading.
+pub enum Combine {
+    /// The median. Spread is the median absolute deviation.
+    Median,
+    /// The mean, after discarding this many of the highest, and lowest conversions.
+    /// Spread is the standard deviation of those kept.
+    TrimmedMean(u8),
+}
+
+#[derive(Clone, Copy, Debug)]
+/// Take several conversions per reading, and combine them, rejecting outliers; eg
+/// spikes from pump and relay EMI. This is applied before the Kalman filter.
+pub struct Oversampling {
+    /// Conversions per reading, from 1 to `MAX_SAMPLES`.
+    pub samples: u8,
+    pub combine: Combine,
+}
+
+impl Default for Oversampling {
+    /// A single conversion per reading.
+    fn default() -> Self {
+        Self {
+            samples: 1,
+            combine: Combine::Median,
+        }
+    }
+}
+
+impl Oversampling {
+    pub fn new(samples: u8, combine: Combine) -> Self {
+        Self { samples, combine }
+    }
+
+    /// Take `samples` readings of `input`, and combine them.
+    pub fn read<A, BUS>(
+        &self,
+        adc: &mut A,
+        input: AdcInput,
+        range: FullScale,
+        bus: &mut BUS,
+    ) -> Result<Sample, A::Error>
+    where
+        A: VoltageSource<BUS>,
+    {
+        let n = self.count();
+
+        let mut buf = [0.; MAX_SAMPLES];
+        for V in buf[..n].iter_mut() {
+            *V = adc.read_voltage(input, range, bus)?;
+        }
+
+        Ok(self.combine(&mut buf[..n]))
+    }
+
+    /// Conversions per reading, limited to 1 to `MAX_SAMPLES`.
+    pub(crate) fn count(&self) -> usize {
+        (self.samples as usize).clamp(1, MAX_SAMPLES)
+    }
+
+    /// Combine conversions into one reading. Reorders `samples`.
+    pub(crate) fn combine(&self, samples: &mut [f32]) -> Sample {
+        let n = samples.len();
+        samples.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+        match self.combine {
+            Combine::Median => {
+                let value = median(samples);
+                for V in samples.iter_mut() {
+                    *V = (*V - value).abs();
+                }
+                samples.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+                Sample::new(value, median(samples))
+            }
+            Combine::TrimmedMean(trim) => {
+                // Keep at least one conversion.
+                let trim = (trim as usize).min((n - 1) / 2);
+                let kept = &samples[trim..n - trim];
+
+                let value = kept.iter().sum::<f32>() / kept.len() as f32;
+                let var = kept.iter().map(|V| (V - value).powi(2)).sum::<f32>() / kept.len() as f32;
+
+                Sample::new(value, var.sqrt())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// A reading combined from several conversions, with their spread, in the same units.
+pub struct Sample {
+    pub value: f32,
+    pub spread: f32,
+}
+
+impl Sample {
+    pub fn new(value: f32, spread: f32) -> Self {
+        Self { value, spread }
+    }
+
+    /// Convert to other units with `f`, eg from V to pH. The spread is scaled by the
+    /// local slope of `f`, so it stays meaningful for nonlinear conversions.
+    pub fn map(self, f: impl Fn(f32) -> f32) -> Self {
+        Self {
+            value: f(self.value),
+            spread: (f(self.value + self.spread) - f(self.value - self.spread)).abs() / 2.,
+        }
+    }
+}
+
+/// The median of sorted values.
+fn median(sorted: &[f32]) -> f32 {
+    let n = sorted.len();
+    if n % 2 == 1 {
+        sorted[n / 2]
+    } else {
+        (sorted[n / 2 - 1] + sorted[n / 2]) / 2.
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// Errors reading an `I2cAdc`, or calibrating any `VoltageSource` with `AdcCal`.
+pub enum AdcError<E> {
+    /// An error on the I2C bus, or from the voltage source being calibrated
+    Bus(E),
+    /// The part doesn't have the input requested; eg the onboard temperature sensor's
+    /// channel, on a single-input part
+    NoInput,
+    /// The part can't measure the full scale range requested; eg above ±2.048V on the
+    /// MCP342x
+    Range,
+    /// Gain calibration read the reference input at the offset voltage, or with a 0V
+    /// reference, so gain can't be found
+    Gain,
+}
+
+impl<E> From<AdcError<E>> for SensorError {
+    fn from(e: AdcError<E>) -> Self {
+        match e {
+            AdcError::Bus(_) => Self::Bus,
+            AdcError::NoInput | AdcError::Range | AdcError::Gain => Self::BadMeasurement,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// An ADC on the I2C bus.
+///
+/// # Remarks
+///
+/// Parts with a single input (ADS1113, ADS1114 and MCP3421) only measure
+/// `AdcInput::Probe`; use an off-board temperature source with them.
+pub struct I2cAdc {
+    pub model: AdcModel,
+    pub addr: u8,
+}
+
+impl I2cAdc {
+    pub fn new(model: AdcModel, addr: u8) -> Self {
+        Self { model, addr }
+    }
+
+    /// Take a reading, in Volts.
+    pub fn read_voltage<I2C, E>(
+        &self,
+        input: AdcInput,
+        range: FullScale,
+        i2c: &mut I2C,
+    ) -> Result<f32, AdcError<E>>
+    where
+        I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+    {
+        self.start(input, range, i2c)?;
+
+        // Wait until the conversion is complete.
+        loop {
+            if let Some(V) = self.poll(range, i2c)? {
+                return Ok(V);
+            }
+        }
+    }
+
+    /// Start a one-shot conversion, without waiting for it. Collect the result with
+    /// `poll`. This lets conversions on several ADCs run at once.
+    pub fn start<I2C, E>(
+        &self,
+        input: AdcInput,
+        range: FullScale,
+        i2c: &mut I2C,
+    ) -> Result<(), AdcError<E>>
+    where
+        I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+    {
+        if !self.model.has_input(input) {
+            return Err(AdcError::NoInput);
+        }
+        if !self.model.has_range(range) {
+            return Err(AdcError::Range);
+        }
+
+        match self.model {
+            AdcModel::Mcp342x { resolution, .. } => self.start_mcp(input, range, resolution, i2c),
+            // The ADS1113 has no PGA.
+            AdcModel::Ads1113 => self.start_ads(input, FullScale::V2_048, i2c),
+            _ => self.start_ads(input, range, i2c),
+        }
+        .map_err(AdcError::Bus)
+    }
+
+    /// Check if the conversion started with `start` is complete. If so, return its
+    /// result, in Volts. `range` must match the one passed to `start`.
+    pub fn poll<I2C, E>(&self, range: FullScale, i2c: &mut I2C) -> Result<Option<f32>, AdcError<E>>
+    where
+        I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+    {
+        if let AdcModel::Mcp342x { resolution, .. } = self.model {
+            return self.poll_mcp(range, resolution, i2c).map_err(AdcError::Bus);
+        }
+
+        let raw = match self.poll_ads(i2c).map_err(AdcError::Bus)? {
+            Some(r) => r,
+            None => return Ok(None),
+        };
+
+        Ok(Some(ads_voltage(self.model, raw, range)))
+    }
+
+    /// Command a one-shot measurement on an ADS1x15.
+    fn start_ads<I2C, E>(&self, input: AdcInput, range: FullScale, i2c: &mut I2C) -> Result<(), E>
+    where
+        I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+    {
+        let mux = match input {
+            AdcInput::Probe => 0,
+            AdcInput::Single(channel) => channel.single_ended_mux(),
+        };
+        let cmd = ADS_CMD | mux | (range.ads_pga_bits() << 9);
+
+        // Set up the cfg, and command a one-shot reading. Note that we
+        // pass the 16-bit i2c command as 2 bytes.
+        i2c.write(self.addr, &[CFG_REG, (cmd >> 8) as u8, cmd as u8])
+    }
+
+    /// Return an ADS1x15's conversion register, if the conversion is complete.
+    fn poll_ads<I2C, E>(&self, i2c: &mut I2C) -> Result<Option<i16>, E>
+    where
+        I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+    {
+        let mut buf = [0, 0];
+        i2c.write_read(self.addr, &[CFG_REG], &mut buf)?;
+        // First of 16 cfg reg bits is 0 while converting, 1 when ready. (when reading)
+        if buf[0] >> 7 == 0 {
+            return Ok(None);
+        }
+
+        // Read the result from the conversion register.
+        let mut result_buf: [u8; 2] = [0, 0];
+        i2c.write_read(self.addr, &[CONV_REG], &mut result_buf)?;
+
+        Ok(Some(i16::from_be_bytes([result_buf[0], result_buf[1]])))
+    }
+
+    /// Command a one-shot measurement on an MCP342x.
+    fn start_mcp<I2C, E>(
+        &self,
+        input: AdcInput,
+        range: FullScale,
+        resolution: McpResolution,
+        i2c: &mut I2C,
+    ) -> Result<(), E>
+    where
+        I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+    {
+        let channel = match input {
+            AdcInput::Probe | AdcInput::Single(AdcChannel::A0) => 0,
+            AdcInput::Single(AdcChannel::A1) => 1,
+            AdcInput::Single(AdcChannel::A2) => 2,
+            AdcInput::Single(AdcChannel::A3) => 3,
+        };
+        let (_, gain_bits) = range.mcp_gain();
+
+        // See MCP342x datasheet, Register 5-1. Setting RDY in one-shot mode starts a
+        // conversion.
+        let cfg = (1 << 7) | (channel << 5) | (resolution.sample_rate_bits() << 2) | gain_bits;
+        i2c.write(self.addr, &[cfg])
+    }
+
+    /// Return an MCP342x's result, in Volts, if the conversion is complete.
+    fn poll_mcp<I2C, E>(
+        &self,
+        range: FullScale,
+        resolution: McpResolution,
+        i2c: &mut I2C,
+    ) -> Result<Option<f32>, E>
+    where
+        I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+    {
+        // Data bytes are followed by the config byte, whose RDY bit clears when the
+        // conversion is complete. 18-bit results take 3 data bytes; others take 2.
+        let mut buf = [0; 4];
+        let len = if let McpResolution::Bits18 = resolution {
+            4
+        } else {
+            3
+        };
+
+        i2c.read(self.addr, &mut buf[..len])?;
+        if buf[len - 1] >> 7 != 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(mcp_voltage(&buf[..len - 1], range, resolution)))
+    }
+}
+
+/// Convert an ADS1x15 conversion register to Volts.
+fn ads_voltage(model: AdcModel, raw: i16, range: FullScale) -> f32 {
+    match model {
+        AdcModel::Ads1113 => raw as f32 / 32_768. * FullScale::V2_048.volts(),
+        // The 12-bit result is left-aligned.
+        AdcModel::Ads1015 => (raw >> 4) as f32 / 2_048. * range.volts(),
+        _ => raw as f32 / 32_768. * range.volts(),
+    }
+}
+
+/// Convert MCP342x data bytes to Volts: 3 bytes at 18 bits, and 2 otherwise.
+fn mcp_voltage(data: &[u8], range: FullScale, resolution: McpResolution) -> f32 {
+    let code = if let McpResolution::Bits18 = resolution {
+        // Sign-extend from 18 bits.
+        let raw = i32::from_be_bytes([0, data[0], data[1], data[2]]);
+        (raw << 14) >> 14
+    } else {
+        // Results below 16 bits are sign-extended by the chip.
+        i16::from_be_bytes([data[0], data[1]]) as i32
+    };
+
+    let (gain, _) = range.mcp_gain();
+    let lsb = MCP_VREF / (1 << (resolution.bits() - 1)) as f32;
+    code as f32 * lsb / gain
+}
+
+impl<I2C, E> VoltageSource<I2C> for I2cAdc
+where
+    I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+{
+    type Error = AdcError<E>;
+
+    fn read_voltage(
+        &mut self,
+        input: AdcInput,
+        range: FullScale,
+        i2c: &mut I2C,
+    ) -> Result<f32, AdcError<E>> {
+        I2cAdc::read_voltage(self, input, range, i2c)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// Errors reading a `OneShotAdc`.
+pub enum OneShotError<E> {
+    /// An error from the ADC peripheral
+    Adc(E),
+    /// No pin is assigned to the input requested
+    NoInput,
+    /// The resolution isn't from 1 to 32 bits
+    Resolution,
+}
+
+/// Reads sensor voltages with an MCU's onboard ADC, through
+/// `embedded_hal::adc::OneShot`. The ADC peripheral is passed as the bus when reading.
+/// `ADC` is the HAL's ADC marker type, and `Word` its result type.
+///
+/// # Remarks
+///
+/// `AdcInput::Probe` reads `probe`, and `AdcInput::Single(AdcChannel::A2)` reads `temp`,
+/// the onboard temperature sensor's output. Other inputs return
+/// `OneShotError::NoInput`. The probe amplifier's output must be single-ended, in the ADC's
+/// range; set `bias` to the voltage it outputs for 0V at the probe. `FullScale` is
+/// ignored; readings use `vref`.
+pub struct OneShotAdc<ADC, Word, P, TP = P> {
+    pub probe: P,
+    pub temp: Option<TP>,
+    /// ADC reference voltage, in V.
+    pub vref: f32,
+    /// ADC resolution, in bits.
+    pub resolution: u8,
+    /// Subtracted from probe readings, in V.
+    pub bias: f32,
+    _adc: PhantomData<(ADC, Word)>,
+}
+
+impl<ADC, Word, P> OneShotAdc<ADC, Word, P> {
+    /// Read the probe on `probe`, with no onboard temperature sensor. `resolution` must
+    /// be from 1 to 32 bits.
+    pub fn new(probe: P, vref: f32, resolution: u8) -> Result<Self, OneShotError<Infallible>> {
+        Self::init(probe, None, vref, resolution)
+    }
+}
+
+impl<ADC, Word, P, TP> OneShotAdc<ADC, Word, P, TP> {
+    /// Read the probe on `probe`, and the onboard temperature sensor on `temp`.
+    /// `resolution` must be from 1 to 32 bits.
+    pub fn new_with_temp(
+        probe: P,
+        temp: TP,
+        vref: f32,
+        resolution: u8,
+    ) -> Result<Self, OneShotError<Infallible>> {
+        Self::init(probe, Some(temp), vref, resolution)
+    }
+
+    fn init(
+        probe: P,
+        temp: Option<TP>,
+        vref: f32,
+        resolution: u8,
+    ) -> Result<Self, OneShotError<Infallible>> {
+        if !valid_resolution(resolution) {
+            return Err(OneShotError::Resolution);
+        }
+
+        Ok(Self {
+            probe,
+            temp,
+            vref,
+            resolution,
+            bias: 0.,
+            _adc: PhantomData,
+        })
+    }
+
+    /// Convert a reading to Volts.
+    fn voltage<E>(&self, word: Word) -> Result<f32, OneShotError<E>>
+    where
+        Word: Into<u32>,
+    {
+        // `resolution` is public, so may have changed since construction.
+        if !valid_resolution(self.resolution) {
+            return Err(OneShotError::Resolution);
+        }
+
+        let max = ((1_u64 << self.resolution) - 1) as f32;
+        Ok(word.into() as f32 / max * self.vref)
+    }
+}
+
+fn valid_resolution(resolution: u8) -> bool {
+    (1..=32).contains(&resolution)
+}
+
+impl<ADC, ADCP, Word, P, TP, E> VoltageSource<ADCP> for OneShotAdc<ADC, Word, P, TP>
+where
+    P: Channel<ADC>,
+    TP: Channel<ADC>,
+    ADCP: OneShot<ADC, Word, P, Error = E> + OneShot<ADC, Word, TP, Error = E>,
+    Word: Into<u32>,
+{
+    type Error = OneShotError<E>;
+
+    fn read_voltage(
+        &mut self,
+        input: AdcInput,
+        _range: FullScale,
+        adc: &mut ADCP,
+    ) -> Result<f32, Self::Error> {
+        match input {
+            AdcInput::Probe => {
+                let word = nb::block!(adc.read(&mut self.probe)).map_err(OneShotError::Adc)?;
+                Ok(self.voltage(word)? - self.bias)
+            }
+            AdcInput::Single(AdcChannel::A2) => match &mut self.temp {
+                Some(pin) => {
+                    let word = nb::block!(adc.read(pin)).map_err(OneShotError::Adc)?;
+                    self.voltage(word)
+                }
+                None => Err(OneShotError::NoInput),
+            },
+            _ => Err(OneShotError::NoInput),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads a fixed voltage on every input.
+    struct Fixed(f32);
+
+    impl VoltageSource<()> for Fixed {
+        type Error = Infallible;
+
+        fn read_voltage(
+            &mut self,
+            _: AdcInput,
+            _: FullScale,
+            _: &mut (),
+        ) -> Result<f32, Infallible> {
+            Ok(self.0)
+        }
+    }
+
+    #[test]
+    fn measure_gain() {
+        let mut cal = AdcCal::new(0.002, 1.);
+
+        let gain = cal.measure_gain(
+            &mut Fixed(1.002),
+            AdcInput::Probe,
+            FullScale::V2_048,
+            1.01,
+            &mut (),
+        );
+        assert!((gain.unwrap() - 1.01).abs() < 1e-5);
+        assert!((cal.apply(1.002) - 1.01).abs() < 1e-5);
+
+        // A shorted reference reads at the offset.
+        let shorted = cal.measure_gain(
+            &mut Fixed(0.002),
+            AdcInput::Probe,
+            FullScale::V2_048,
+            1.01,
+            &mut (),
+        );
+        assert!(matches!(shorted, Err(AdcError::Gain)));
+        assert!((cal.gain - 1.01).abs() < 1e-5);
+    }
+
+    #[test]
+    fn combine_rejects_spike() {
+        let samples = [0.100, 0.101, 0.099, 0.100, 1.5, 0.100, 0.101, 0.099];
+
+        for &combine in &[Combine::Median, Combine::TrimmedMean(1)] {
+            let mut buf = samples;
+            let sample = Oversampling::new(8, combine).combine(&mut buf);
+
+            assert!((sample.value - 0.1).abs() < 0.001, "{:?}", sample);
+            assert!(sample.spread < 0.01, "{:?}", sample);
+        }
+    }
+
+    #[test]
+    fn one_shot_resolution() {
+        type Adc = OneShotAdc<(), u32, ()>;
+
+        assert!(matches!(
+            Adc::new((), 3.3, 0),
+            Err(OneShotError::Resolution)
+        ));
+        assert!(matches!(
+            Adc::new((), 3.3, 33),
+            Err(OneShotError::Resolution)
+        ));
+
+        let mut adc = Adc::new((), 3.3, 32).unwrap();
+        let v: Result<f32, OneShotError<()>> = adc.voltage(u32::MAX);
+        assert!((v.unwrap() - 3.3).abs() < 1e-5);
+
+        adc.resolution = 64;
+        let v: Result<f32, OneShotError<()>> = adc.voltage(1);
+        assert!(matches!(v, Err(OneShotError::Resolution)));
+    }
+
+    #[test]
+    fn ads1015_decode() {
+        let range = FullScale::V2_048;
+        // The 12-bit code is left-aligned in the conversion register.
+        let v = ads_voltage(AdcModel::Ads1015, 0x7ff0, range);
+        assert!((v - 2_047. / 2_048. * 2.048).abs() < 1e-6);
+
+        let v = ads_voltage(AdcModel::Ads1015, 0xfff0_u16 as i16, range);
+        assert!((v + 2.048 / 2_048.).abs() < 1e-6);
+
+        let v = ads_voltage(AdcModel::Ads1115, 0x7ff0, range);
+        assert!((v - 0x7ff0 as f32 / 32_768. * 2.048).abs() < 1e-6);
+    }
+
+    #[test]
+    fn mcp_18_bit_decode() {
+        let res = McpResolution::Bits18;
+        let lsb = 2.048 / 131_072.;
+
+        let v = mcp_voltage(&[0x01, 0xff, 0xff], FullScale::V2_048, res);
+        assert!((v - 131_071. * lsb).abs() < 1e-6);
+
+        // The top 6 bits of the first byte repeat the sign bit.
+        let v = mcp_voltage(&[0xff, 0xff, 0xff], FullScale::V2_048, res);
+        assert!((v + lsb).abs() < 1e-7);
+
+        let v = mcp_voltage(&[0xfe, 0x00, 0x00], FullScale::V2_048, res);
+        assert!((v + 2.048).abs() < 1e-6);
+
+        // 16-bit results, with gain.
+        let v = mcp_voltage(&[0x80, 0x00], FullScale::V0_256, McpResolution::Bits16);
+        assert!((v + 0.256).abs() < 1e-6);
+    }
+}
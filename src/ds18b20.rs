@@ -0,0 +1,552 @@
+//! Supports the DS18B20 digital thermometer, common in waterproof probes. Probes share a
+//! 1-Wire bus on a single open-drain pin, which we bit-bang. Like the other sensors here,
+//! each `Ds18b20` borrows the bus when reading, so several can share one `OneWire`.
+
+use embedded_hal::{
+    blocking::delay::{DelayMs, DelayUs},
+    digital::v2::{InputPin, OutputPin},
+};
+
+use crate::{Board, SensorError, TemperatureSource};
+
+// ROM commands. From Datasheet, Figure 12.
+const SEARCH_ROM: u8 = 0xf0;
+const MATCH_ROM: u8 = 0x55;
+const SKIP_ROM: u8 = 0xcc;
+// Function commands.
+const CONVERT_T: u8 = 0x44;
+const WRITE_SCRATCHPAD: u8 = 0x4e;
+const READ_SCRATCHPAD: u8 = 0xbe;
+
+const FAMILY_CODE: u8 = 0x28;
+// The temperature register's value at power-on, 85°C. See Table 1.
+const POWER_ON_TEMP: i16 = 0x0550;
+
+#[derive(Clone, Copy, Debug)]
+/// Errors from the 1-Wire bus, or a DS18B20.
+pub enum Ds18b20Error<PE> {
+    /// An error reading or setting the bus pin.
+    Pin(PE),
+    /// No device responded to a reset with a presence pulse.
+    NoDevice,
+    /// Data read failed its CRC check, eg from noise on long leads.
+    Crc,
+    /// The scratchpad read as all zeros, which passes the CRC check; eg from the bus
+    /// being shorted to ground.
+    Shorted,
+    /// The temperature read is the 85°C power-on value, so no conversion completed;
+    /// eg from the probe resetting, or too little power for a parasite-powered probe.
+    NoConversion,
+}
+
+impl<PE> From<Ds18b20Error<PE>> for SensorError {
+    fn from(e: Ds18b20Error<PE>) -> Self {
+        match e {
+            Ds18b20Error::Pin(_) | Ds18b20Error::Shorted => Self::Bus,
+            Ds18b20Error::NoDevice => Self::NotConnected,
+            Ds18b20Error::Crc | Ds18b20Error::NoConversion => Self::BadMeasurement,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// Conversion resolution. Higher resolutions take longer; 12 bits takes 750ms.
+pub enum Resolution {
+    Bits9,
+    Bits10,
+    Bits11,
+    Bits12,
+}
+
+impl Resolution {
+    /// Maximum conversion time, in ms. See Table 2.
+    pub fn conversion_time(&self) -> u16 {
+        match self {
+            Self::Bits9 => 94,
+            Self::Bits10 => 188,
+            Self::Bits11 => 375,
+            Self::Bits12 => 750,
+        }
+    }
+
+    /// Config register R1:R0 bits. See Figure 10.
+    fn bits(&self) -> u8 {
+        match self {
+            Self::Bits9 => 0b00,
+            Self::Bits10 => 0b01,
+            Self::Bits11 => 0b10,
+            Self::Bits12 => 0b11,
+        }
+    }
+
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => Self::Bits9,
+            0b01 => Self::Bits10,
+            0b10 => Self::Bits11,
+            _ => Self::Bits12,
+        }
+    }
+}
+
+/// A 1-Wire bus, on an open-drain pin with a pull-up. Owns the pin.
+pub struct OneWire<P> {
+    pin: P,
+}
+
+impl<P, PE> OneWire<P>
+where
+    P: InputPin<Error = PE> + OutputPin<Error = PE>,
+{
+    /// Create the bus, releasing the pin so it idles high.
+    pub fn new(mut pin: P) -> Result<Self, Ds18b20Error<PE>> {
+        pin.set_high().map_err(Ds18b20Error::Pin)?;
+        Ok(Self { pin })
+    }
+
+    /// Reset the bus. Returns `true` if any device responded with a presence pulse.
+    pub fn reset<D: DelayUs<u16>>(&mut self, delay: &mut D) -> Result<bool, Ds18b20Error<PE>> {
+        self.pin.set_low().map_err(Ds18b20Error::Pin)?;
+        delay.delay_us(480);
+        self.pin.set_high().map_err(Ds18b20Error::Pin)?;
+        delay.delay_us(70);
+
+        let present = self.pin.is_low().map_err(Ds18b20Error::Pin)?;
+        delay.delay_us(410);
+
+        Ok(present)
+    }
+
+    fn write_bit<D: DelayUs<u16>>(
+        &mut self,
+        delay: &mut D,
+        bit: bool,
+    ) -> Result<(), Ds18b20Error<PE>> {
+        // Write time slots are 60us or longer; hold the bus low for most of it to
+        // write a 0, or release it early to write a 1.
+        let low_time = if bit { 6 } else { 60 };
+
+        self.pin.set_low().map_err(Ds18b20Error::Pin)?;
+        delay.delay_us(low_time);
+        self.pin.set_high().map_err(Ds18b20Error::Pin)?;
+        delay.delay_us(70 - low_time);
+
+        Ok(())
+    }
+
+    fn read_bit<D: DelayUs<u16>>(&mut self, delay: &mut D) -> Result<bool, Ds18b20Error<PE>> {
+        self.pin.set_low().map_err(Ds18b20Error::Pin)?;
+        delay.delay_us(6);
+        self.pin.set_high().map_err(Ds18b20Error::Pin)?;
+        // Sample near the end of the 15us the device drives the bus for.
+        delay.delay_us(9);
+
+        let bit = self.pin.is_high().map_err(Ds18b20Error::Pin)?;
+        delay.delay_us(55);
+
+        Ok(bit)
+    }
+
+    /// Write a byte, LSB first.
+    pub fn write_byte<D: DelayUs<u16>>(
+        &mut self,
+        delay: &mut D,
+        byte: u8,
+    ) -> Result<(), Ds18b20Error<PE>> {
+        for i in 0..8 {
+            self.write_bit(delay, byte & (1 << i) > 0)?;
+        }
+        Ok(())
+    }
+
+    /// Read a byte, LSB first.
+    pub fn read_byte<D: DelayUs<u16>>(&mut self, delay: &mut D) -> Result<u8, Ds18b20Error<PE>> {
+        let mut byte = 0;
+        for i in 0..8 {
+            if self.read_bit(delay)? {
+                byte |= 1 << i;
+            }
+        }
+        Ok(byte)
+    }
+
+    /// Reset the bus, and address a device by ROM code, or all devices if `None`.
+    pub fn select<D: DelayUs<u16>>(
+        &mut self,
+        delay: &mut D,
+        rom: Option<&[u8; 8]>,
+    ) -> Result<(), Ds18b20Error<PE>> {
+        if !self.reset(delay)? {
+            return Err(Ds18b20Error::NoDevice);
+        }
+
+        match rom {
+            Some(r) => {
+                self.write_byte(delay, MATCH_ROM)?;
+                for byte in r {
+                    self.write_byte(delay, *byte)?;
+                }
+            }
+            None => self.write_byte(delay, SKIP_ROM)?,
+        }
+
+        Ok(())
+    }
+
+    /// Find the ROM codes of DS18B20s on the bus, filling `roms`. Returns the number
+    /// found, up to `roms.len()`.
+    ///
+    /// # Remarks
+    ///
+    /// Uses the search algorithm from Maxim Application Note 187: each pass walks the
+    /// 64 ROM bits, and takes the other branch at the last bit where devices disagreed.
+    pub fn search<D: DelayUs<u16>>(
+        &mut self,
+        delay: &mut D,
+        roms: &mut [[u8; 8]],
+    ) -> Result<usize, Ds18b20Error<PE>> {
+        let mut count = 0;
+        let mut rom = [0; 8];
+        // 1-indexed bit positions; 0 means none.
+        let mut last_discrepancy = 0;
+
+        while count < roms.len() {
+            if !self.reset(delay)? {
+                break;
+            }
+            self.write_byte(delay, SEARCH_ROM)?;
+
+            let mut last_zero = 0;
+
+            for i in 1..=64 {
+                let (byte, mask) = ((i - 1) / 8, 1 << ((i - 1) % 8));
+
+                let id_bit = self.read_bit(delay)?;
+                let cmp_bit = self.read_bit(delay)?;
+
+                let dir = match (id_bit, cmp_bit) {
+                    // No device participated in this pass.
+                    (true, true) => return Ok(count),
+                    // All remaining devices agree on this bit.
+                    (true, false) => true,
+                    (false, true) => false,
+                    // Devices disagree.
+                    (false, false) => {
+                        let dir = if i < last_discrepancy {
+                            rom[byte] & mask > 0
+                        } else {
+                            i == last_discrepancy
+                        };
+                        if !dir {
+                            last_zero = i;
+                        }
+                        dir
+                    }
+                };
+
+                if dir {
+                    rom[byte] |= mask;
+                } else {
+                    rom[byte] &= !mask;
+                }
+                self.write_bit(delay, dir)?;
+            }
+
+            if crc8(&rom[..7]) != rom[7] {
+                return Err(Ds18b20Error::Crc);
+            }
+
+            if rom[0] == FAMILY_CODE {
+                roms[count] = rom;
+                count += 1;
+            }
+
+            last_discrepancy = last_zero;
+            if last_discrepancy == 0 {
+                break;
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+/// A DS18B20 on a 1-Wire bus.
+pub struct Ds18b20 {
+    /// The device's ROM code, or `None` to address it with Skip ROM, if it's the only
+    /// device on the bus.
+    pub rom: Option<[u8; 8]>,
+    resolution: Resolution,
+}
+
+impl Ds18b20 {
+    /// Create a DS18B20 with a ROM code, eg from `OneWire::search`.
+    pub fn new(rom: [u8; 8]) -> Self {
+        Self {
+            rom: Some(rom),
+            resolution: Resolution::Bits12,
+        }
+    }
+
+    /// Create a DS18B20 that's the only device on its bus.
+    pub fn new_single() -> Self {
+        Self {
+            rom: None,
+            resolution: Resolution::Bits12,
+        }
+    }
+
+    /// Read the 9-byte scratchpad, checking its CRC.
+    pub fn read_scratchpad<P, PE, D>(
+        &mut self,
+        bus: &mut OneWire<P>,
+        delay: &mut D,
+    ) -> Result<[u8; 9], Ds18b20Error<PE>>
+    where
+        P: InputPin<Error = PE> + OutputPin<Error = PE>,
+        D: DelayUs<u16>,
+    {
+        bus.select(delay, self.rom.as_ref())?;
+        bus.write_byte(delay, READ_SCRATCHPAD)?;
+
+        let mut buf = [0; 9];
+        for byte in buf.iter_mut() {
+            *byte = bus.read_byte(delay)?;
+        }
+
+        if buf.iter().all(|b| *b == 0) {
+            return Err(Ds18b20Error::Shorted);
+        }
+
+        if crc8(&buf[..8]) != buf[8] {
+            return Err(Ds18b20Error::Crc);
+        }
+
+        Ok(buf)
+    }
+
+    /// Set conversion resolution. This is stored in the device's scratchpad, so must
+    /// be set again after it loses power.
+    pub fn set_resolution<P, PE, D>(
+        &mut self,
+        bus: &mut OneWire<P>,
+        delay: &mut D,
+        resolution: Resolution,
+    ) -> Result<(), Ds18b20Error<PE>>
+    where
+        P: InputPin<Error = PE> + OutputPin<Error = PE>,
+        D: DelayUs<u16>,
+    {
+        // Keep the alarm thresholds, which share the write.
+        let scratchpad = self.read_scratchpad(bus, delay)?;
+
+        bus.select(delay, self.rom.as_ref())?;
+        bus.write_byte(delay, WRITE_SCRATCHPAD)?;
+        bus.write_byte(delay, scratchpad[2])?;
+        bus.write_byte(delay, scratchpad[3])?;
+        bus.write_byte(delay, (resolution.bits() << 5) | 0b1_1111)?;
+
+        self.resolution = resolution;
+        Ok(())
+    }
+
+    /// Read conversion resolution from the device.
+    pub fn read_resolution<P, PE, D>(
+        &mut self,
+        bus: &mut OneWire<P>,
+        delay: &mut D,
+    ) -> Result<Resolution, Ds18b20Error<PE>>
+    where
+        P: InputPin<Error = PE> + OutputPin<Error = PE>,
+        D: DelayUs<u16>,
+    {
+        let scratchpad = self.read_scratchpad(bus, delay)?;
+        self.resolution = Resolution::from_bits(scratchpad[4] >> 5);
+        Ok(self.resolution)
+    }
+
+    /// Measure temperature, in °C. Blocks for the conversion time of the resolution.
+    /// A reading of exactly 85°C returns `Ds18b20Error::NoConversion`.
+    pub fn read<P, PE, D>(
+        &mut self,
+        bus: &mut OneWire<P>,
+        delay: &mut D,
+    ) -> Result<f32, Ds18b20Error<PE>>
+    where
+        P: InputPin<Error = PE> + OutputPin<Error = PE>,
+        D: DelayUs<u16> + DelayMs<u16>,
+    {
+        bus.select(delay, self.rom.as_ref())?;
+        bus.write_byte(delay, CONVERT_T)?;
+        delay.delay_ms(self.resolution.conversion_time());
+
+        let scratchpad = self.read_scratchpad(bus, delay)?;
+
+        // A reading of exactly 85°C is indistinguishable from the power-on value, so
+        // we reject it.
+        if scratchpad[..2] == POWER_ON_TEMP.to_le_bytes() {
+            return Err(Ds18b20Error::NoConversion);
+        }
+
+        // Bits below the resolution are undefined.
+        let undefined = 3 - self.resolution.bits();
+        let raw = i16::from_le_bytes([scratchpad[0], scratchpad[1]]) & !((1 << undefined) - 1);
+
+        Ok(raw as f32 / 16.)
+    }
+
+    /// Borrow this probe with its bus and delay, so it can be used as a temperature
+    /// source, eg for pH compensation.
+    pub fn source<'a, P, D>(
+        &'a mut self,
+        bus: &'a mut OneWire<P>,
+        delay: &'a mut D,
+    ) -> Ds18b20Source<'a, P, D> {
+        Ds18b20Source {
+            sensor: self,
+            bus,
+            delay,
+        }
+    }
+}
+
+/// A `Ds18b20`, borrowed with its bus and delay. Created with `Ds18b20::source`.
+pub struct Ds18b20Source<'a, P, D> {
+    sensor: &'a mut Ds18b20,
+    bus: &'a mut OneWire<P>,
+    delay: &'a mut D,
+}
+
+impl<'a, P, PE, D, A, BUS> TemperatureSource<A, BUS> for Ds18b20Source<'a, P, D>
+where
+    P: InputPin<Error = PE> + OutputPin<Error = PE>,
+    D: DelayUs<u16> + DelayMs<u16>,
+{
+    type Error = Ds18b20Error<PE>;
+
+    fn read_temp(&mut self, _board: &mut Board<A, BUS>) -> Result<f32, Self::Error> {
+        self.sensor.read(self.bus, self.delay)
+    }
+}
+
+/// The Dallas/Maxim 1-Wire CRC: polynomial x^8 + x^5 + x^4 + 1, LSB first.
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0;
+
+    for byte in data {
+        let mut b = *byte;
+        for _ in 0..8 {
+            let mix = (crc ^ b) & 1;
+            crc >>= 1;
+            if mix != 0 {
+                crc ^= 0x8c;
+            }
+            b >>= 1;
+        }
+    }
+
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{cell::Cell, convert::Infallible};
+
+    use super::*;
+
+    /// A bus pin with a device that always answers resets, and sends `scratchpad` in
+    /// read slots.
+    struct Dq {
+        scratchpad: [u8; 9],
+        bit: Cell<usize>,
+    }
+
+    impl Dq {
+        fn new(scratchpad: [u8; 9]) -> Self {
+            Self {
+                scratchpad,
+                bit: Cell::new(0),
+            }
+        }
+    }
+
+    impl InputPin for Dq {
+        type Error = Infallible;
+
+        fn is_high(&self) -> Result<bool, Infallible> {
+            let i = self.bit.get();
+            self.bit.set(i + 1);
+            Ok(self.scratchpad[(i / 8) % 9] & (1 << (i % 8)) != 0)
+        }
+
+        // Only read for presence pulses.
+        fn is_low(&self) -> Result<bool, Infallible> {
+            Ok(true)
+        }
+    }
+
+    impl OutputPin for Dq {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    struct Delay;
+
+    impl DelayUs<u16> for Delay {
+        fn delay_us(&mut self, _us: u16) {}
+    }
+
+    impl DelayMs<u16> for Delay {
+        fn delay_ms(&mut self, _ms: u16) {}
+    }
+
+    /// A scratchpad with temperature `raw`, and a valid CRC.
+    fn scratchpad(raw: i16) -> [u8; 9] {
+        let t = raw.to_le_bytes();
+        let mut r = [t[0], t[1], 0x4b, 0x46, 0x7f, 0xff, 0x0c, 0x10, 0];
+        r[8] = crc8(&r[..8]);
+        r
+    }
+
+    fn read(scratchpad: [u8; 9]) -> Result<f32, Ds18b20Error<Infallible>> {
+        let mut bus = OneWire::new(Dq::new(scratchpad)).unwrap();
+        Ds18b20::new_single().read(&mut bus, &mut Delay)
+    }
+
+    #[test]
+    fn read_temp() {
+        // 25.0625°C, from Table 1.
+        assert_eq!(read(scratchpad(0x0191)).unwrap(), 25.0625);
+        assert_eq!(read(scratchpad(-0x0192)).unwrap(), -25.125);
+    }
+
+    #[test]
+    fn read_rejects_shorted_bus() {
+        assert!(matches!(read([0; 9]), Err(Ds18b20Error::Shorted)));
+    }
+
+    #[test]
+    fn read_rejects_power_on_value() {
+        assert!(matches!(
+            read(scratchpad(POWER_ON_TEMP)),
+            Err(Ds18b20Error::NoConversion)
+        ));
+    }
+
+    #[test]
+    fn crc8_matches_rom() {
+        // The example ROM from Maxim Application Note 27.
+        let rom = [0x02, 0x1c, 0xb8, 0x01, 0x00, 0x00, 0x00, 0xa2];
+
+        assert_eq!(crc8(&rom[..7]), rom[7]);
+        // Including the CRC byte leaves no remainder.
+        assert_eq!(crc8(&rom), 0);
+    }
+}
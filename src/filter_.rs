@@ -3,9 +3,11 @@ use nalgebra::{
     dimension::{U1, U2},
     Matrix1, Matrix2, Vector2,
 };
-use num_traits::float::FloatCore;
+#[allow(unused_imports)]
+use num_traits::float::FloatCore; // Required for `powi` in `no_std`.
 
 /// Initialize the kalman filter. dt is the time between measurements, in seconds.
+#[allow(clippy::field_reassign_with_default)]
 pub fn create(_dt: f32, std: f32) -> KalmanFilter<f32, U2, U1, U1> {
     let mut kf = KalmanFilter::default();
 
@@ -0,0 +1,150 @@
+//! The analog front end shared by the pH, ORP and ISE sensors: the ADC reading the
+//! probe, its calibration and oversampling, and the board's onboard temperature sensor.
+
+use crate::{
+    temp, AdcCal, AdcError, AdcInput, Board, CalPtT, CalSlot, Error, FullScale, Oversampling,
+    Sample, TempSensorModel, TemperatureSource, VoltageSource,
+};
+
+/// An ADC, with the probe on `AdcInput::Probe`, and the onboard temperature sensor on
+/// `AdcInput::Single(AdcChannel::A2)`. `adc_cal` corrects readings of both.
+pub struct FrontEnd<A> {
+    pub adc: A,
+    pub adc_cal: AdcCal,
+    pub oversampling: Oversampling,
+    pub temp_sensor: TempSensorModel,
+    pub temp_cal_1: Option<CalPtT>,
+    pub temp_cal_2: Option<CalPtT>,
+}
+
+impl<A> FrontEnd<A> {
+    /// Create a front end with an LM61 temperature sensor, and no calibration.
+    pub fn new(adc: A) -> Self {
+        Self {
+            adc,
+            adc_cal: AdcCal::default(),
+            oversampling: Oversampling::default(),
+            temp_sensor: TempSensorModel::Lm61,
+            temp_cal_1: None,
+            temp_cal_2: None,
+        }
+    }
+
+    /// Read the probe voltage. Corrected with the ADC calibration, and combined from
+    /// `oversampling.samples` conversions.
+    pub fn read_voltage<BUS>(&mut self, bus: &mut BUS) -> Result<f32, A::Error>
+    where
+        A: VoltageSource<BUS>,
+    {
+        Ok(self.read_voltage_sampled(bus)?.value)
+    }
+
+    /// Read voltage, as with `read_voltage`, along with the spread of the conversions
+    /// it's combined from, in V. A large spread indicates noise, eg from EMI.
+    pub fn read_voltage_sampled<BUS>(&mut self, bus: &mut BUS) -> Result<Sample, A::Error>
+    where
+        A: VoltageSource<BUS>,
+    {
+        let sample =
+            self.oversampling
+                .read(&mut self.adc, AdcInput::Probe, FullScale::V2_048, bus)?;
+
+        Ok(Sample::new(
+            self.adc_cal.apply(sample.value),
+            sample.spread * self.adc_cal.gain.abs(),
+        ))
+    }
+
+    /// Read the onboard temperature sensor, in °C.
+    pub fn read_temp<BUS>(&mut self, bus: &mut BUS) -> Result<f32, A::Error>
+    where
+        A: VoltageSource<BUS>,
+    {
+        self.board(bus).read_onboard_temp()
+    }
+
+    /// Calibrate the onboard temperature sensor, by measuring its voltage while
+    /// `reference` reads the true temperature; eg a reference thermometer passed as
+    /// `TempSource::OffBoard`, or an `Rtd`. 1 point corrects offset; 2 correct offset
+    /// and slope. Set the calibration, and return (Voltage, Temp). `CalSlot::Three`
+    /// is treated as `CalSlot::Two`.
+    pub fn calibrate_temp<BUS, S>(
+        &mut self,
+        slot: CalSlot,
+        mut reference: S,
+        bus: &mut BUS,
+    ) -> Result<(f32, f32), Error<A::Error, S::Error>>
+    where
+        A: VoltageSource<BUS>,
+        S: TemperatureSource<A, BUS>,
+    {
+        let pt = temp::measure_cal_pt(&mut reference, self.board(bus))?;
+
+        match slot {
+            CalSlot::One => self.temp_cal_1 = Some(pt),
+            CalSlot::Two | CalSlot::Three => self.temp_cal_2 = Some(pt),
+        }
+        Ok((pt.V, pt.T))
+    }
+
+    pub fn calibrate_temp_all(&mut self, pt0: Option<CalPtT>, pt1: Option<CalPtT>) {
+        self.temp_cal_1 = pt0;
+        self.temp_cal_2 = pt1;
+    }
+
+    pub fn reset_temp_calibration(&mut self) {
+        self.temp_cal_1 = None;
+        self.temp_cal_2 = None;
+    }
+
+    /// Measure the ADC's offset, with `input` at 0V: `AdcInput::Probe` with a shorting
+    /// cap on the probe connector, or a single-ended input tied to ground, where the
+    /// board allows. Do this before calibrating the sensor, since it changes the
+    /// voltages measured. Sets the offset, and returns it, in V.
+    pub fn calibrate_adc_offset<BUS>(
+        &mut self,
+        input: AdcInput,
+        bus: &mut BUS,
+    ) -> Result<f32, A::Error>
+    where
+        A: VoltageSource<BUS>,
+    {
+        self.adc_cal
+            .measure_offset(&mut self.adc, input, FullScale::V2_048, bus)
+    }
+
+    /// Measure the ADC's gain, with a known voltage `V_ref`, in V, applied to the probe
+    /// input. Measure offset first. Sets the gain, and returns it.
+    pub fn calibrate_adc_gain<BUS>(
+        &mut self,
+        V_ref: f32,
+        bus: &mut BUS,
+    ) -> Result<f32, AdcError<A::Error>>
+    where
+        A: VoltageSource<BUS>,
+    {
+        self.adc_cal.measure_gain(
+            &mut self.adc,
+            AdcInput::Probe,
+            FullScale::V2_048,
+            V_ref,
+            bus,
+        )
+    }
+
+    pub fn reset_adc_calibration(&mut self) {
+        self.adc_cal = AdcCal::default();
+    }
+
+    /// The board this front end is on, for reading temperature sources.
+    pub(crate) fn board<'a, BUS>(&'a mut self, bus: &'a mut BUS) -> Board<'a, A, BUS> {
+        Board::new(
+            &mut self.adc,
+            bus,
+            self.adc_cal,
+            self.temp_sensor,
+            self.temp_cal_1,
+            self.temp_cal_2,
+        )
+    }
+}
@@ -0,0 +1,429 @@
+//! Support for ion-selective electrodes (ISE), eg nitrate, phosphate and potassium.
+//! We model the electrode with the Nernst equation: `V = E0 + S * log10(C)`, where
+//! `S = ln(10) * R * T / (z * F)` is the slope per decade of concentration for an ion
+//! of charge `z`. Calibration fits `E0` (and `S`, with 2 or more standards) in
+//! log-concentration space.
+
+use filter::kalman::kalman_filter::KalmanFilter;
+
+use nalgebra::{
+    dimension::{U1, U2},
+    Vector1,
+};
+#[allow(unused_imports)]
+use num_traits::float::Float; // Required for `log10` and `powf` in `no_std`.
+
+use crate::{
+    filter_, temp::read_source, AdcModel, CalPtIse, CalSlot, Error, FrontEnd, I2cAdc, Sample,
+    TemperatureSource, VoltageSource, ADC_ADDR_1, ADC_ADDR_2,
+};
+
+const R: f32 = 8.314_463; // Gas constant, J/(mol*K)
+const F: f32 = 96_485.33; // Faraday constant, C/mol
+const KELVIN: f32 = 273.15;
+const T_REF: f32 = 25.; // °C. Calibration slopes are normalized to this temperature.
+
+// We filter in pX (-log10 of concentration in mol/L), so these are comparable to pH.
+const DISCRETE_PX_JUMP_THRESH: f32 = 0.2;
+const PX_STD: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The ion an electrode is selective for.
+pub enum Ion {
+    /// NO₃⁻
+    Nitrate,
+    /// H₂PO₄⁻; the dominant phosphate species from pH 2 to 7.
+    DihydrogenPhosphate,
+    /// HPO₄²⁻; the dominant phosphate species from pH 7 to 12.
+    HydrogenPhosphate,
+    /// K⁺
+    Potassium,
+}
+
+impl Ion {
+    /// Ionic charge.
+    pub fn charge(&self) -> i8 {
+        match self {
+            Self::Nitrate => -1,
+            Self::DihydrogenPhosphate => -1,
+            Self::HydrogenPhosphate => -2,
+            Self::Potassium => 1,
+        }
+    }
+
+    /// Molar mass, in g/mol.
+    pub fn molar_mass(&self) -> f32 {
+        match self {
+            Self::Nitrate => 62.004,
+            Self::DihydrogenPhosphate => 96.987,
+            Self::HydrogenPhosphate => 95.979,
+            Self::Potassium => 39.098,
+        }
+    }
+
+    /// Theoretical (Nernstian) electrode slope at temperature `T` (°C), in V per
+    /// decade of concentration. Negative for anions.
+    pub fn nernst_slope(&self, T: f32) -> f32 {
+        core::f32::consts::LN_10 * R * (T + KELVIN) / (self.charge() as f32 * F)
+    }
+
+    /// Convert a concentration in `unit` to mol/L.
+    pub fn to_mol_per_l(&self, conc: f32, unit: ConcUnit) -> f32 {
+        match unit {
+            // mg/L -> g/L -> mol/L
+            ConcUnit::MgPerL | ConcUnit::Ppm => conc / 1_000. / self.molar_mass(),
+            ConcUnit::MolPerL => conc,
+        }
+    }
+
+    /// Convert a concentration in mol/L to `unit`.
+    pub fn from_mol_per_l(&self, conc: f32, unit: ConcUnit) -> f32 {
+        match unit {
+            ConcUnit::MgPerL | ConcUnit::Ppm => conc * self.molar_mass() * 1_000.,
+            ConcUnit::MolPerL => conc,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Concentration units used for readings and calibration standards.
+pub enum ConcUnit {
+    /// Milligrams of the ion per liter
+    MgPerL,
+    /// Parts per million by mass. We assume a dilute aqueous solution with a density
+    /// of 1kg/L, so this is numerically equal to mg/L.
+    Ppm,
+    /// Moles of the ion per liter
+    MolPerL,
+}
+
+pub struct IseSensor<A = I2cAdc> {
+    pub ion: Ion,
+    /// Unit used for readings, and for standards passed to `calibrate`.
+    pub unit: ConcUnit,
+    pub filter: KalmanFilter<f32, U2, U1, U1>,
+    pub dt: f32, // used for manually resetting the filter (`filterpy` has a reset method, `filter-rs` doesn't).
+    last_meas: f32, // pX, to let discrete jumps bypass the filter.
+    /// Voltage offset introduced by the ionic-strength adjuster (ISA), eg when it's
+    /// added to samples but not to standards. Subtracted from sample readings, in Volts.
+    pub isa_offset: f32,
+    pub cal_1: CalPtIse,
+    pub cal_2: Option<CalPtIse>,
+    pub cal_3: Option<CalPtIse>,
+    pub front_end: FrontEnd<A>,
+}
+
+impl IseSensor {
+    /// Create a new sensor, with a default single-point calibration for `ion`.
+    /// `dt` is in seconds.
+    pub fn new(ion: Ion, unit: ConcUnit, dt: f32) -> Self {
+        Self::new_with_adc(I2cAdc::new(AdcModel::Ads1115, ADC_ADDR_1), ion, unit, dt)
+    }
+
+    /// Create a new sensor with an ADC I2C address of 0x49.
+    pub fn new_alt_addr(ion: Ion, unit: ConcUnit, dt: f32) -> Self {
+        Self::new_with_adc(I2cAdc::new(AdcModel::Ads1115, ADC_ADDR_2), ion, unit, dt)
+    }
+}
+
+impl<A> IseSensor<A> {
+    /// Create a new sensor, read with `adc`. See `PhSensor::new_with_adc`.
+    pub fn new_with_adc(adc: A, ion: Ion, unit: ConcUnit, dt: f32) -> Self {
+        Self {
+            ion,
+            unit,
+            filter: filter_::create(dt, PX_STD),
+            dt,
+            last_meas: 7.,
+            isa_offset: 0.,
+            cal_1: default_cal(ion),
+            cal_2: None,
+            cal_3: None,
+            front_end: FrontEnd::new(adc),
+        }
+    }
+
+    /// Make a prediction using the Kalman filter. Not generally used directly.
+    pub fn predict(&mut self) {
+        self.filter.predict(None, None, None, None)
+    }
+
+    /// Update the Kalman filter with a reading. Not generally used directly.
+    pub fn update<BUS, S>(&mut self, t: S, bus: &mut BUS) -> Result<(), Error<A::Error, S::Error>>
+    where
+        A: VoltageSource<BUS>,
+        S: TemperatureSource<A, BUS>,
+    {
+        let conc = self.read_raw(t, bus)?;
+        self.update_filter(self.px_from_conc(conc));
+        Ok(())
+    }
+
+    /// Update the Kalman filter with a pX reading.
+    fn update_filter(&mut self, pX: f32) {
+        let z = Vector1::new(pX);
+
+        if (pX - self.last_meas).abs() > DISCRETE_PX_JUMP_THRESH {
+            self.filter = filter_::create(self.dt, PX_STD) // reset the filter.
+        }
+        self.last_meas = pX;
+
+        self.filter.update(&z, None, None);
+    }
+
+    /// Take a concentration reading in `self.unit`, using the Kalman filter. This
+    /// reduces sensor noise, and provides a more accurate reading.
+    pub fn read<BUS, S>(&mut self, t: S, bus: &mut BUS) -> Result<f32, Error<A::Error, S::Error>>
+    where
+        A: VoltageSource<BUS>,
+        S: TemperatureSource<A, BUS>,
+    {
+        self.predict();
+        self.update(t, bus)?;
+        // self.filter.x is mean, variance. We only care about the mean
+        Ok(self.conc_from_px(self.filter.x[0]))
+    }
+
+    /// Take a concentration reading in `self.unit`, without using the Kalman filter.
+    pub fn read_raw<BUS, S>(
+        &mut self,
+        t: S,
+        bus: &mut BUS,
+    ) -> Result<f32, Error<A::Error, S::Error>>
+    where
+        A: VoltageSource<BUS>,
+        S: TemperatureSource<A, BUS>,
+    {
+        Ok(self.read_raw_sampled(t, bus)?.value)
+    }
+
+    /// Take a concentration reading, as with `read_raw`, along with the spread of the
+    /// conversions it's combined from, in `self.unit`. A large spread indicates noise,
+    /// eg from EMI.
+    pub fn read_raw_sampled<BUS, S>(
+        &mut self,
+        mut t: S,
+        bus: &mut BUS,
+    ) -> Result<Sample, Error<A::Error, S::Error>>
+    where
+        A: VoltageSource<BUS>,
+        S: TemperatureSource<A, BUS>,
+    {
+        let T = read_source(&mut t, self.front_end.board(bus))?;
+        let V = self
+            .front_end
+            .read_voltage_sampled(bus)
+            .map_err(Error::Bus)?;
+
+        Ok(V.map(|V| {
+            let pX = px_from_voltage(
+                V - self.isa_offset,
+                T,
+                self.ion,
+                &self.cal_1,
+                &self.cal_2,
+                &self.cal_3,
+            );
+            self.conc_from_px(pX)
+        }))
+    }
+
+    /// Useful for getting calibration data. See `FrontEnd::read_voltage`.
+    pub fn read_voltage<BUS>(&mut self, bus: &mut BUS) -> Result<f32, A::Error>
+    where
+        A: VoltageSource<BUS>,
+    {
+        self.front_end.read_voltage(bus)
+    }
+
+    /// Useful for getting calibration data
+    pub fn read_temp<BUS>(&mut self, bus: &mut BUS) -> Result<f32, A::Error>
+    where
+        A: VoltageSource<BUS>,
+    {
+        self.front_end.read_temp(bus)
+    }
+
+    /// Calibrate by measuring voltage and temp in a standard of concentration `conc`,
+    /// in `self.unit`. Set the calibration, and return (Voltage, Temp).
+    pub fn calibrate<BUS, S>(
+        &mut self,
+        slot: CalSlot,
+        conc: f32,
+        mut t: S,
+        bus: &mut BUS,
+    ) -> Result<(f32, f32), Error<A::Error, S::Error>>
+    where
+        A: VoltageSource<BUS>,
+        S: TemperatureSource<A, BUS>,
+    {
+        let T = read_source(&mut t, self.front_end.board(bus))?;
+        let V = self.read_voltage(bus).map_err(Error::Bus)?;
+        let pt = CalPtIse::new(V, self.ion.to_mol_per_l(conc, self.unit), T);
+
+        match slot {
+            CalSlot::One => self.cal_1 = pt,
+            CalSlot::Two => self.cal_2 = Some(pt),
+            CalSlot::Three => self.cal_3 = Some(pt),
+        }
+        Ok((V, T))
+    }
+
+    pub fn calibrate_all(&mut self, pt0: CalPtIse, pt1: Option<CalPtIse>, pt2: Option<CalPtIse>) {
+        self.cal_1 = pt0;
+        self.cal_2 = pt1;
+        self.cal_3 = pt2;
+    }
+
+    pub fn reset_calibration(&mut self) {
+        self.cal_1 = default_cal(self.ion);
+        self.cal_2 = None;
+        self.cal_3 = None;
+    }
+
+    /// The calibrated electrode slope, as a fraction of the theoretical Nernstian
+    /// slope. Healthy electrodes are generally between 0.9 and 1.05.
+    pub fn slope_ratio(&self) -> f32 {
+        let (_, S) = fit(self.ion, &self.cal_1, &self.cal_2, &self.cal_3);
+        S / self.ion.nernst_slope(T_REF)
+    }
+
+    /// Convert pX to a concentration in `self.unit`.
+    fn conc_from_px(&self, pX: f32) -> f32 {
+        self.ion.from_mol_per_l(10_f32.powf(-pX), self.unit)
+    }
+
+    /// Convert a concentration in `self.unit` to pX.
+    fn px_from_conc(&self, conc: f32) -> f32 {
+        -self.ion.to_mol_per_l(conc, self.unit).log10()
+    }
+}
+
+/// A single-point calibration at 100mg/L and 0V, relying on the Nernstian slope.
+fn default_cal(ion: Ion) -> CalPtIse {
+    CalPtIse::new(0., ion.to_mol_per_l(100., ConcUnit::MgPerL), T_REF)
+}
+
+/// Fit the electrode's calibration points with a line in log-concentration space,
+/// using least squares. Each point's log concentration is scaled by its absolute
+/// temperature, so the result is normalized to `T_REF`. With a single point, we use
+/// the theoretical slope. Returns (E0, slope), in V and V per decade.
+fn fit(
+    ion: Ion,
+    cal_0: &CalPtIse,
+    cal_1: &Option<CalPtIse>,
+    cal_2: &Option<CalPtIse>,
+) -> (f32, f32) {
+    let scaled_x = |pt: &CalPtIse| pt.C.log10() * (pt.T + KELVIN) / (T_REF + KELVIN);
+
+    let mut n = 0.;
+    let mut sum_x = 0.;
+    let mut sum_y = 0.;
+    let mut sum_xx = 0.;
+    let mut sum_xy = 0.;
+
+    for pt in [Some(*cal_0), *cal_1, *cal_2].iter().flatten() {
+        let x = scaled_x(pt);
+        n += 1.;
+        sum_x += x;
+        sum_y += pt.V;
+        sum_xx += x * x;
+        sum_xy += x * pt.V;
+    }
+
+    let denom = n * sum_xx - sum_x * sum_x;
+
+    // Use the theoretical slope for a single point, or if all standards have the
+    // same concentration.
+    let S = if n < 2. || denom.abs() < f32::EPSILON {
+        ion.nernst_slope(T_REF)
+    } else {
+        (n * sum_xy - sum_x * sum_y) / denom
+    };
+    let E0 = (sum_y - S * sum_x) / n;
+
+    (E0, S)
+}
+
+/// Convert voltage to pX (-log10 of concentration in mol/L), using the Nernst
+/// equation. Temperature compensated. Input `T` is in Celsius.
+fn px_from_voltage(
+    V: f32,
+    T: f32,
+    ion: Ion,
+    cal_0: &CalPtIse,
+    cal_1: &Option<CalPtIse>,
+    cal_2: &Option<CalPtIse>,
+) -> f32 {
+    let (E0, S) = fit(ion, cal_0, cal_1, cal_2);
+    // The slope scales with absolute temperature.
+    let S_T = S * (T + KELVIN) / (T_REF + KELVIN);
+
+    -(V - E0) / S_T
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const E0: f32 = 0.2;
+    // 95% of the Nernstian slope, as from a slightly aged electrode.
+    const SLOPE_FRACTION: f32 = 0.95;
+
+    /// The calibration point an electrode with `E0`, and a slope of `SLOPE_FRACTION`,
+    /// would read in a standard of `C` mol/L at `T` °C.
+    fn pt(ion: Ion, C: f32, T: f32) -> CalPtIse {
+        let S = SLOPE_FRACTION * ion.nernst_slope(T);
+        CalPtIse::new(E0 + S * C.log10(), C, T)
+    }
+
+    #[test]
+    fn fit_recovers_slope_and_e0() {
+        let ion = Ion::Nitrate;
+        let S = SLOPE_FRACTION * ion.nernst_slope(T_REF);
+
+        // Standards at a non-reference temperature.
+        let pts = [pt(ion, 1e-4, 15.), pt(ion, 1e-3, 15.), pt(ion, 1e-2, 15.)];
+
+        for &(pt1, pt2) in &[(Some(pts[1]), None), (Some(pts[1]), Some(pts[2]))] {
+            let (E0_fit, S_fit) = fit(ion, &pts[0], &pt1, &pt2);
+            assert!((E0_fit - E0).abs() < 1e-4, "E0: {}", E0_fit);
+            assert!((S_fit - S).abs() < 1e-4, "S: {}", S_fit);
+        }
+    }
+
+    #[test]
+    fn px_compensates_temperature() {
+        let ion = Ion::Potassium;
+        let (cal_1, cal_2) = (pt(ion, 1e-4, 20.), pt(ion, 1e-2, 20.));
+
+        // A 1mmol/L sample, read at 35°C.
+        let V = pt(ion, 1e-3, 35.).V;
+        let pX = px_from_voltage(V, 35., ion, &cal_1, &Some(cal_2), &None);
+
+        assert!((pX - 3.).abs() < 0.01, "pX: {}", pX);
+    }
+
+    #[test]
+    fn slope_ratio() {
+        let mut sensor = IseSensor::new(Ion::Nitrate, ConcUnit::MolPerL, 1.);
+        sensor.calibrate_all(
+            pt(Ion::Nitrate, 1e-4, 30.),
+            Some(pt(Ion::Nitrate, 1e-2, 30.)),
+            None,
+        );
+
+        assert!((sensor.slope_ratio() - SLOPE_FRACTION).abs() < 0.001);
+    }
+
+    #[test]
+    fn concentration_units() {
+        let ion = Ion::Nitrate;
+
+        // 1mmol/L of NO₃⁻ is 62.004mg/L.
+        let mol = ion.to_mol_per_l(62.004, ConcUnit::MgPerL);
+        assert!((mol - 1e-3).abs() < 1e-8);
+        assert!((ion.from_mol_per_l(mol, ConcUnit::Ppm) - 62.004).abs() < 1e-3);
+        assert_eq!(ion.to_mol_per_l(0.5, ConcUnit::MolPerL), 0.5);
+    }
+}
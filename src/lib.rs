@@ -3,7 +3,7 @@
 //! [Github repository](https://github.com/anyleaf/ph-rust) for
 //! complete examples on the Rasperry Pi and stm32f3.
 //!
-//! Example for Rasperry Pi, and other ph_linux systems:
+//! Example for Rasperry Pi, and other Linux systems:
 //! Cargo.toml:
 //! ```toml
 //! [package]
@@ -13,71 +13,113 @@
 //! edition = "2018"
 //!
 //! [dependencies]
-//! embedded-hal = "^0.2.3"
-//! ph_linux-embedded-hal = "^0.3.0"
-//! anyleaf = "^0.1.6"
+//! embedded-hal = "^0.2.4"
+//! linux-embedded-hal = "^0.3.0"
+//! anyleaf = "^0.1.7"
 //! ```
 //!
 //! main.rs:
-//! ```rust
-//!use embedded_hal::blocking::delay::DelayMs;
-//!use linux_embedded_hal::{Delay, I2cdev};
-//!use anyleaf::{PhSensor, CalPt, CalSlot, TempSource};
+//! ```rust,no_run
+//! # mod linux_embedded_hal {
+//! #     use embedded_hal::blocking::{delay::DelayMs, i2c};
+//! #     pub struct I2cdev;
+//! #     impl I2cdev {
+//! #         pub fn new(_path: &str) -> Result<Self, ()> { Ok(Self) }
+//! #     }
+//! #     impl i2c::Read for I2cdev {
+//! #         type Error = ();
+//! #         fn read(&mut self, _: u8, _: &mut [u8]) -> Result<(), ()> { Ok(()) }
+//! #     }
+//! #     impl i2c::Write for I2cdev {
+//! #         type Error = ();
+//! #         fn write(&mut self, _: u8, _: &[u8]) -> Result<(), ()> { Ok(()) }
+//! #     }
+//! #     impl i2c::WriteRead for I2cdev {
+//! #         type Error = ();
+//! #         fn write_read(&mut self, _: u8, _: &[u8], _: &mut [u8]) -> Result<(), ()> { Ok(()) }
+//! #     }
+//! #     pub struct Delay;
+//! #     impl DelayMs<u16> for Delay {
+//! #         fn delay_ms(&mut self, _: u16) {}
+//! #     }
+//! # }
+//! use embedded_hal::blocking::delay::DelayMs;
+//! use linux_embedded_hal::{Delay, I2cdev};
+//! use anyleaf::{CalPt, CalSlot, PhSensor, TempSource};
 //!
-//!fn main() {
-//!    let i2c = I2cdev::new("/dev/i2c-1").unwrap();
-//!    let dt = 1.; // Time between measurements, in seconds
-//!    let mut ph_sensor = PhSensor::new(i2c, dt);
+//! fn main() {
+//!     let mut i2c = I2cdev::new("/dev/i2c-1").unwrap();
+//!     let dt = 1.; // Time between measurements, in seconds
+//!     let mut ph_sensor = PhSensor::new(dt);
 //!
-//!    // 2 or 3 pt calibration both give acceptable results.
-//!    // Calibrate with known values. (voltage, pH, temp in °C).
-//!    // You can find voltage and temperature with `ph_sensor.read_voltage()` and
-//!    // `ph_sensor.read_temp()` respectively.
-//!    // For 3 pt calibration, pass a third argument to `calibrate_all`.
-//!    ph_sensor.calibrate_all(
-//!        CalPt::new(0., 7., 25.), CalPt::new(0.17, 4., 25.), None,
-//!    );
+//!     // 2 or 3 pt calibration both give acceptable results.
+//!     // Calibrate with known values. (voltage, pH, temp in °C).
+//!     // You can find voltage and temperature with `ph_sensor.read_voltage(&mut i2c)`
+//!     // and `ph_sensor.read_temp(&mut i2c)` respectively.
+//!     // For 3 pt calibration, pass a third argument to `calibrate_all`.
+//!     ph_sensor.calibrate_all(CalPt::new(0., 7., 25.), CalPt::new(0.17, 4., 25.), None);
 //!
-//!    // Or, call these with the sensor in the appropriate buffer solution.
-//!    // This will automatically use voltage and temperature.
-//!    // Voltage and Temp are returned, but calibration occurs
-//!    // without using the return values.
-//!    // (V, T) = ph_sensor.calibrate(CalSlot::One, 7.);
-//!    // ph_sensor.calibrate(CalSlot::Two, 4.);
+//!     // Or, call these with the sensor in the appropriate buffer solution.
+//!     // This will automatically use voltage and temperature.
+//!     // Voltage and Temp are returned, but calibration occurs
+//!     // without using the return values.
+//!     // let (V, T) = ph_sensor.calibrate(CalSlot::One, 7., TempSource::OnBoard, &mut i2c).unwrap();
+//!     // ph_sensor.calibrate(CalSlot::Two, 4., TempSource::OnBoard, &mut i2c).unwrap();
 //!
-//!    // Store the calibration parameters somewhere, so they persist
-//!    // between program runs.
+//!     // Store the calibration parameters somewhere, so they persist
+//!     // between program runs; eg with `ph_sensor.cal_to_bytes()`.
 //!
-//!    let mut delay = Delay {};
+//!     let mut delay = Delay {};
 //!
-//!    loop {
-//!        let pH = ph_sensor.read(TempSource::OnBoard).unwrap();
-//!        println!("pH: {}", pH);
+//!     loop {
+//!         let pH = ph_sensor.read(TempSource::OnBoard, &mut i2c).unwrap();
+//!         println!("pH: {}", pH);
 //!
-//!        delay.delay_ms(dt as u16 * 1000);
-//!    }
-//!}
+//!         delay.delay_ms(dt as u16 * 1000);
+//!     }
+//! }
 //! ```
 
 #![no_std]
 #![allow(non_snake_case, clippy::needless_doctest_main)]
-#![feature(unsize)] // Used by the `max31865` module.
 
-use embedded_hal::blocking::i2c::{Write, WriteRead};
 use filter::kalman::kalman_filter::KalmanFilter;
 
 use nalgebra::{
     dimension::{U1, U2},
     Vector1,
 };
-// use nb::block;
-use num_traits::float::FloatCore; // Required to take absolute value in `no_std`.
 
+// Modules import `num_traits` float traits for math methods that `core` lacks. When
+// `std` is linked, eg in tests, inherent methods take priority, so those imports are
+// marked `#[allow(unused_imports)]`.
+pub mod adc;
+pub mod ds18b20;
 mod filter_;
+pub mod front_end;
+pub mod ise;
 pub mod rtd;
+pub mod scheduler;
 mod storage;
+pub mod temp;
+pub mod thermistor;
 
-pub use rtd::{Rtd, RtdType, Wires};
+pub use adc::{
+    AdcCal, AdcError, AdcInput, AdcModel, Combine, FullScale, I2cAdc, McpResolution, OneShotAdc,
+    OneShotError, Oversampling, Sample, VoltageSource, MAX_SAMPLES,
+};
+pub use ds18b20::{Ds18b20, Ds18b20Error, OneWire, Resolution as Ds18b20Resolution};
+pub use front_end::FrontEnd;
+pub use ise::{ConcUnit, Ion, IseSensor};
+pub use rtd::{
+    CalPtRtd, CvdCoeffs, FaultCycle, FaultStatus, NoDrdy, Rtd, RtdConfig, RtdError, RtdType, Wires,
+};
+pub use scheduler::{read_parallel, read_snapshot, Conversion, Snapshot};
+pub use storage::{ISE_CAL_SIZE, ORP_CAL_SIZE, PH_CAL_SIZE, RTD_CAL_SIZE};
+pub use temp::{Board, TempSensorModel, TemperatureSource};
+pub use thermistor::{CalPtThermistor, Divider, Thermistor, ThermistorModel};
+
+use temp::read_source;
 
 // Compensate for temperature diff between readings and calibration.
 const PH_TEMP_C: f32 = -0.05694; // pH/(V*T). V is in volts, and T is in °C
@@ -85,17 +127,11 @@ const DISCRETE_PH_JUMP_THRESH: f32 = 0.2;
 const DISCRETE_ORP_JUMP_THRESH: f32 = 30.;
 const PH_STD: f32 = 0.1;
 const ORP_STD: f32 = 10.;
+// The ORP amplifier has unity gain; used to judge calibration quality.
+const ORP_GAIN_IDEAL: f32 = 1_000.; // mV/V
 
 const ADC_ADDR_1: u8 = 0x48;
 const ADC_ADDR_2: u8 = 0x49;
-const CFG_REG: u8 = 0x1;
-const CONV_REG: u8 = 0x0;
-
-// See ads1115 datasheet Section 9.6.3: Config Register. Start a differential conversion on channels
-// 0 and 1, With 2.048V full scale range, one-shot mode, no alert pin activity.
-const PH_ORP_CMD: u16 = 0b1000_0101_1000_0000;
-// Same as `PH_ORP_CM`, but with channel A2.
-const T_CMD: u16 = 0b1110_0101_1000_0000;
 
 #[derive(Debug, Clone, Copy)]
 /// Keeps our calibration organized, so we track when to overwrite.
@@ -106,7 +142,31 @@ pub enum CalSlot {
 }
 
 #[derive(Debug, Clone, Copy)]
-/// Specify onboard or offboard temperature source.
+/// An ADS1115 input, for single-ended readings. On AnyLeaf boards, A0 and A1 are
+/// the probe, A2 is the onboard temperature sensor, and A3 is free.
+pub enum AdcChannel {
+    A0,
+    A1,
+    A2,
+    A3,
+}
+
+impl AdcChannel {
+    /// Config register MUX bits, for a single-ended reading on this channel.
+    pub(crate) fn single_ended_mux(&self) -> u16 {
+        let channel = match self {
+            Self::A0 => 0,
+            Self::A1 => 1,
+            Self::A2 => 2,
+            Self::A3 => 3,
+        };
+        (0b100 | channel) << 12
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Specify onboard or offboard temperature source. For other sources, such as
+/// an `Rtd`, see `TemperatureSource`.
 pub enum TempSource {
     OnBoard,
     OffBoard(f32),
@@ -139,6 +199,80 @@ impl CalPtOrp {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+/// Data for a single ion-selective electrode calibration point.
+pub struct CalPtIse {
+    pub V: f32, // voltage, in Volts
+    pub C: f32, // concentration, in mol/L
+    pub T: f32, // in Celsius
+}
+
+impl CalPtIse {
+    pub fn new(V: f32, C: f32, T: f32) -> Self {
+        Self { V, C, T }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The reference electrode an ORP probe measures against.
+pub enum OrpReference {
+    /// Silver/silver chloride, with 3M KCl fill: +210mV vs SHE at 25°C.
+    AgAgCl3M,
+    /// Saturated calomel electrode (SCE): +241mV vs SHE at 25°C.
+    SaturatedCalomel,
+    /// Standard hydrogen electrode. ORP readings are Eh directly.
+    She,
+}
+
+impl OrpReference {
+    /// Potential of the reference electrode vs SHE at temperature `T` (°C), in mV.
+    pub fn potential(&self, T: f32) -> f32 {
+        let T_diff = T - 25.;
+
+        match self {
+            Self::AgAgCl3M => 210. - 0.73 * T_diff,
+            Self::SaturatedCalomel => 241.2 - 0.67 * T_diff,
+            Self::She => 0.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// ORP calibration standard solutions. Their potentials are temperature-dependent.
+pub enum OrpStandard {
+    /// ZoBell's solution: +430mV vs SHE at 25°C, falling 2.2mV/°C.
+    ZoBell,
+    /// Light's solution: +675mV vs SHE at 25°C. Not temperature compensated; calibrate
+    /// with it near 25°C.
+    Light,
+    /// A standard with a known ORP versus the probe's reference, in mV. Not
+    /// temperature compensated.
+    Custom(f32),
+}
+
+impl OrpStandard {
+    /// The standard's ORP at temperature `T` (°C), versus `reference`, in mV.
+    pub fn orp(&self, T: f32, reference: OrpReference) -> f32 {
+        let T_diff = T - 25.;
+
+        match self {
+            // Nordstrom, 1977, Geochimica et Cosmochimica Acta 41: 1835-1841:
+            // `Eh = 0.43 - 0.0022 * (T - 25)` V, for 0 to 50°C.
+            Self::ZoBell => 430. - 2.2 * T_diff - reference.potential(T),
+            // Light, 1972, Analytical Chemistry 44: 1038-1039, gives Eh at 25°C only.
+            Self::Light => 675. - reference.potential(T),
+            Self::Custom(orp) => *orp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Describes how far an ORP calibration is from an ideal sensor.
+pub struct OrpCalQuality {
+    pub slope: f32,  // Calibrated slope, as a fraction of the ideal slope
+    pub offset: f32, // ORP at 0V, in mV
+}
+
 #[derive(Debug, Clone, Copy)]
 /// Data for a single temperature calibration point.
 pub struct CalPtT {
@@ -167,71 +301,62 @@ impl CalPtEc {
     }
 }
 
-pub struct PhSensor {
-    pub addr: u8,
+pub struct PhSensor<A = I2cAdc> {
     pub filter: KalmanFilter<f32, U2, U1, U1>,
     pub dt: f32, // used for manually resetting the filter (`filterpy` has a reset method, `filter-rs` doesn't).
     last_meas: f32, // to let discrete jumps bypass the filter.
     pub cal_1: CalPt,
     pub cal_2: CalPt,
     pub cal_3: Option<CalPt>,
+    pub front_end: FrontEnd<A>,
 }
 
 impl PhSensor {
     pub fn new(dt: f32) -> Self {
+        Self::new_with_adc(I2cAdc::new(AdcModel::Ads1115, ADC_ADDR_1), dt)
+    }
+
+    /// Create a new sensor with an ADC I2C address of 0x49.
+    pub fn new_alt_addr(dt: f32) -> Self {
+        Self::new_with_adc(I2cAdc::new(AdcModel::Ads1115, ADC_ADDR_2), dt)
+    }
+}
+
+impl<A> PhSensor<A> {
+    /// Create a new sensor, read with `adc`; eg an `I2cAdc` with a different model,
+    /// or a `OneShotAdc` for an MCU's onboard ADC.
+    pub fn new_with_adc(adc: A, dt: f32) -> Self {
         // `dt` is in seconds.
         Self {
-            addr: ADC_ADDR_1,
             filter: filter_::create(dt, PH_STD),
             dt,
             last_meas: 7.,
             cal_1: CalPt::new(0., 7., 23.),
             cal_2: CalPt::new(0.17, 4., 23.),
             cal_3: None,
+            front_end: FrontEnd::new(adc),
         }
     }
 
-    /// Create a new sensor with an ADC I2C address of 0x49.
-    pub fn new_alt_addr(dt: f32) -> Self {
-        Self {
-            addr: ADC_ADDR_2,
-            ..Self::new(dt)
-        }
-    }
-
-    /// Set calibration to a sensible default for nitrate, with unit mg/L
-    pub fn cal_nitrate_default(&mut self) {
-        self.cal_1 = CalPt::new(0.25, -2. * 62_000., 23.);
-        self.cal_2 = CalPt::new(0.4, -5. * 62_000., 23.);
-        self.cal_3 = None;
-    }
-
-    /// Set calibration to a sensible default for phosphate, with unit mg/L
-    pub fn cal_phosphate_default(&mut self) {
-        self.cal_1 = CalPt::new(0.25, -2. * 62_000., 23.);
-        self.cal_2 = CalPt::new(0.4, -5. * 62_000., 23.);
-        self.cal_3 = None;
-    }
-
-    /// Set calibration to a sensible default for potassium, with unit mg/L
-    pub fn cal_potassium_default(&mut self) {
-        self.cal_1 = CalPt::new(0.25, -2. * 62_000., 23.);
-        self.cal_2 = CalPt::new(0.4, -5. * 62_000., 23.);
-        self.cal_3 = None;
-    }
-
     /// Make a prediction using the Kalman filter. Not generally used directly.
     pub fn predict(&mut self) {
         self.filter.predict(None, None, None, None)
     }
 
     /// Update the Kalman filter with a pH reading. Not generally used directly.
-    pub fn update<I2C, E>(&mut self, t: TempSource, i2c: &mut I2C)
+    pub fn update<BUS, S>(&mut self, t: S, bus: &mut BUS) -> Result<(), Error<A::Error, S::Error>>
     where
-        I2C: Write<Error = E> + WriteRead<Error = E>,
+        A: VoltageSource<BUS>,
+        S: TemperatureSource<A, BUS>,
     {
-        let pH = self.read_raw(t, i2c);
+        let pH = self.read_raw(t, bus)?;
+        self.update_filter(pH);
+        Ok(())
+    }
 
+    /// Update the Kalman filter with a pH reading taken from `read_raw`, or
+    /// `ph_from_voltage`.
+    pub(crate) fn update_filter(&mut self, pH: f32) {
         let z = Vector1::new(pH);
 
         if (pH - self.last_meas).abs() > DISCRETE_PH_JUMP_THRESH {
@@ -243,80 +368,92 @@ impl PhSensor {
 
     /// Take a pH reading, using the Kalman filter. This reduces sensor
     /// noise, and provides a more accurate reading.
-    pub fn read<I2C, E>(&mut self, t: TempSource, i2c: &mut I2C) -> f32
+    pub fn read<BUS, S>(&mut self, t: S, bus: &mut BUS) -> Result<f32, Error<A::Error, S::Error>>
     where
-        I2C: Write<Error = E> + WriteRead<Error = E>,
+        A: VoltageSource<BUS>,
+        S: TemperatureSource<A, BUS>,
     {
         self.predict();
-        self.update(t, i2c);
+        self.update(t, bus)?;
         // self.filter.x is mean, variance. We only care about the mean
-        self.filter.x[0]
+        Ok(self.filter.x[0])
     }
 
     /// Take a pH reading, without using the Kalman filter
-    // todo: find the right error type for nb/ads111x
-    // todo: Error type: is this right? Read:Error instead?
-    pub fn read_raw<I2C, E>(&mut self, t: TempSource, i2c: &mut I2C) -> f32
+    pub fn read_raw<BUS, S>(
+        &mut self,
+        t: S,
+        bus: &mut BUS,
+    ) -> Result<f32, Error<A::Error, S::Error>>
     where
-        I2C: Write<Error = E> + WriteRead<Error = E>,
+        A: VoltageSource<BUS>,
+        S: TemperatureSource<A, BUS>,
     {
-        let T = match t {
-            TempSource::OnBoard => {
-                temp_from_voltage(voltage_from_adc(take_reading(self.addr, T_CMD, i2c)))
-            }
-            TempSource::OffBoard(t_) => t_,
-        };
+        Ok(self.read_raw_sampled(t, bus)?.value)
+    }
 
-        // We don't re-use `self.read_voltage` here due to i16/f32 type issues.
-        let pH = ph_from_voltage(
-            // todo: This is hard crashing WM if adc is missing.
-            voltage_from_adc(take_reading(self.addr, PH_ORP_CMD, i2c)),
-            // 2.,
-            T,
-            &self.cal_1,
-            &self.cal_2,
-            &self.cal_3,
-        );
+    /// Take a pH reading, as with `read_raw`, along with the spread of the conversions
+    /// it's combined from, in pH. A large spread indicates noise, eg from EMI.
+    pub fn read_raw_sampled<BUS, S>(
+        &mut self,
+        mut t: S,
+        bus: &mut BUS,
+    ) -> Result<Sample, Error<A::Error, S::Error>>
+    where
+        A: VoltageSource<BUS>,
+        S: TemperatureSource<A, BUS>,
+    {
+        let T = read_source(&mut t, self.front_end.board(bus))?;
+        let V = self
+            .front_end
+            .read_voltage_sampled(bus)
+            .map_err(Error::Bus)?;
+
+        let pH = V.map(|V| ph_from_voltage(V, T, &self.cal_1, &self.cal_2, &self.cal_3));
+        self.last_meas = pH.value;
+        Ok(pH)
+    }
+
+    /// Convert a probe voltage, measured at temperature `T`, to pH, without using the
+    /// Kalman filter.
+    pub(crate) fn ph_from_voltage(&mut self, V: f32, T: f32) -> f32 {
+        let pH = ph_from_voltage(V, T, &self.cal_1, &self.cal_2, &self.cal_3);
 
         self.last_meas = pH;
         pH
     }
 
-    /// Useful for getting calibration data
-    pub fn read_voltage<I2C, E>(&mut self, i2c: &mut I2C) -> f32
+    /// Useful for getting calibration data. See `FrontEnd::read_voltage`.
+    pub fn read_voltage<BUS>(&mut self, bus: &mut BUS) -> Result<f32, A::Error>
     where
-        I2C: Write<Error = E> + WriteRead<Error = E>,
+        A: VoltageSource<BUS>,
     {
-        voltage_from_adc(take_reading(self.addr, PH_ORP_CMD, i2c))
+        self.front_end.read_voltage(bus)
     }
 
     /// Useful for getting calibration data
-    pub fn read_temp<I2C, E>(&mut self, i2c: &mut I2C) -> f32
+    pub fn read_temp<BUS>(&mut self, bus: &mut BUS) -> Result<f32, A::Error>
     where
-        I2C: Write<Error = E> + WriteRead<Error = E>,
+        A: VoltageSource<BUS>,
     {
-        temp_from_voltage(voltage_from_adc(take_reading(self.addr, T_CMD, i2c)))
+        self.front_end.read_temp(bus)
     }
 
     /// Calibrate by measuring voltage and temp at a given pH. Set the
     /// calibration, and return (Voltage, Temp).
-    pub fn calibrate<I2C, E>(
+    pub fn calibrate<BUS, S>(
         &mut self,
         slot: CalSlot,
         pH: f32,
-        t: TempSource,
-        i2c: &mut I2C,
-    ) -> (f32, f32)
+        mut t: S,
+        bus: &mut BUS,
+    ) -> Result<(f32, f32), Error<A::Error, S::Error>>
     where
-        I2C: Write<Error = E> + WriteRead<Error = E>,
+        A: VoltageSource<BUS>,
+        S: TemperatureSource<A, BUS>,
     {
-        let T = match t {
-            TempSource::OnBoard => {
-                temp_from_voltage(voltage_from_adc(take_reading(self.addr, T_CMD, i2c)))
-            }
-            TempSource::OffBoard(t_) => t_,
-        };
-        let V = voltage_from_adc(take_reading(self.addr, PH_ORP_CMD, i2c));
+        let T = read_source(&mut t, self.front_end.board(bus))?;
+        let V = self.read_voltage(bus).map_err(Error::Bus)?;
         let pt = CalPt::new(V, pH, T);
 
         match slot {
@@ -324,7 +461,7 @@ impl PhSensor {
             CalSlot::Two => self.cal_2 = pt,
             CalSlot::Three => self.cal_3 = Some(pt),
         }
-        (V, T)
+        Ok((V, T))
     }
 
     pub fn calibrate_all(&mut self, pt0: CalPt, pt1: CalPt, pt2: Option<CalPt>) {
@@ -340,38 +477,45 @@ impl PhSensor {
     }
 }
 
-pub struct OrpSensor {
+pub struct OrpSensor<A = I2cAdc> {
     // These sensors operate in a similar, minus the conversion from
-    // voltage to measurement, not compensating for temp, and using only 1 cal pt.
-    pub addr: u8,
+    // voltage to measurement, not compensating for temp, and using 1 or 2 cal pts.
     pub filter: KalmanFilter<f32, U2, U1, U1>,
     pub dt: f32, // used for manually resetting the filter (`filterpy` has a reset method, `filter-rs` doesn't).
     last_meas: f32, // to let discrete jumps bypass the filter.
-    pub cal: CalPtOrp,
+    pub reference: OrpReference,
+    pub cal_1: CalPtOrp,
+    pub cal_2: Option<CalPtOrp>,
+    pub front_end: FrontEnd<A>,
 }
 
 impl OrpSensor {
     pub fn new(dt: f32) -> Self {
-        // let adc = Ads1x1x::new_ads1115(i2c, SlaveAddr::default());
-
-        Self {
-            // adc: Some(adc),
-            // addr: SlaveAddr::default(),
-            addr: ADC_ADDR_1,
-            filter: filter_::create(dt, ORP_STD),
-            dt,
-            last_meas: 0.,
-            cal: CalPtOrp::new(0.4, 400.),
-        }
+        Self::new_with_adc(I2cAdc::new(AdcModel::Ads1115, ADC_ADDR_1), dt)
     }
 
     /// This isn't intended to be used by the standalone module, but by the water monitor,
     /// which connects the ORP sensor to the second ADC, although it still uses differential
     /// input A0, A1.
     pub fn new_alt_addr(dt: f32) -> Self {
+        Self::new_with_adc(I2cAdc::new(AdcModel::Ads1115, ADC_ADDR_2), dt)
+    }
+}
+
+impl<A> OrpSensor<A> {
+    /// Create a new sensor, read with `adc`. See `PhSensor::new_with_adc`.
+    pub fn new_with_adc(adc: A, dt: f32) -> Self {
+        // let adc = Ads1x1x::new_ads1115(i2c, SlaveAddr::default());
+
         Self {
-            addr: ADC_ADDR_2,
-            ..Self::new(dt)
+            // adc: Some(adc),
+            filter: filter_::create(dt, ORP_STD),
+            dt,
+            last_meas: 0.,
+            reference: OrpReference::AgAgCl3M,
+            cal_1: CalPtOrp::new(0.4, 400.),
+            cal_2: None,
+            front_end: FrontEnd::new(adc),
         }
     }
 
@@ -381,11 +525,18 @@ impl OrpSensor {
     }
 
     /// Update the Kalman filter with an ORP reading. Not generally used directly.
-    pub fn update<I2C, E>(&mut self, i2c: &mut I2C)
+    pub fn update<BUS>(&mut self, bus: &mut BUS) -> Result<(), A::Error>
     where
-        I2C: Write<Error = E> + WriteRead<Error = E>,
+        A: VoltageSource<BUS>,
     {
-        let ORP = self.read_raw(i2c);
+        let ORP = self.read_raw(bus)?;
+        self.update_filter(ORP);
+        Ok(())
+    }
+
+    /// Update the Kalman filter with an ORP reading taken from `read_raw`, or
+    /// `orp_from_voltage`.
+    pub(crate) fn update_filter(&mut self, ORP: f32) {
         let z = Vector1::new(ORP);
 
         if (ORP - self.last_meas).abs() > DISCRETE_ORP_JUMP_THRESH {
@@ -397,68 +548,136 @@ impl OrpSensor {
 
     /// Take an ORP reading, using the Kalman filter. This reduces sensor
     /// noise, and provides a more accurate reading.
-    pub fn read<I2C, E>(&mut self, i2c: &mut I2C) -> f32
+    pub fn read<BUS>(&mut self, bus: &mut BUS) -> Result<f32, A::Error>
     where
-        I2C: Write<Error = E> + WriteRead<Error = E>,
+        A: VoltageSource<BUS>,
     {
         self.predict();
-        self.update(i2c);
+        self.update(bus)?;
         // self.filter.x is mean, variance. We only care about the mean
-        self.filter.x[0]
+        Ok(self.filter.x[0])
+    }
+
+    /// Take a reading of Eh (ORP versus SHE), using the Kalman filter. This
+    /// converts from the probe's reference electrode at the measured temperature, so
+    /// readings from different probes are comparable. Output is in mV.
+    pub fn read_eh<BUS, S>(
+        &mut self,
+        mut t: S,
+        bus: &mut BUS,
+    ) -> Result<f32, Error<A::Error, S::Error>>
+    where
+        A: VoltageSource<BUS>,
+        S: TemperatureSource<A, BUS>,
+    {
+        let T = read_source(&mut t, self.front_end.board(bus))?;
+
+        Ok(self.read(bus).map_err(Error::Bus)? + self.reference.potential(T))
     }
 
     /// Take an ORP reading, without using the Kalman filter
-    pub fn read_raw<I2C, E>(&mut self, i2c: &mut I2C) -> f32
+    pub fn read_raw<BUS>(&mut self, bus: &mut BUS) -> Result<f32, A::Error>
     where
-        I2C: Write<Error = E> + WriteRead<Error = E>,
+        A: VoltageSource<BUS>,
     {
-        // We don't re-use `self.read_voltage`, due to i16 vs f32 typing issues.
+        Ok(self.read_raw_sampled(bus)?.value)
+    }
 
-        let orp = orp_from_voltage(
-            voltage_from_adc(take_reading(self.addr, PH_ORP_CMD, i2c)),
-            &self.cal,
-        );
+    /// Take an ORP reading, as with `read_raw`, along with the spread of the
+    /// conversions it's combined from, in mV. A large spread indicates noise, eg from
+    /// EMI.
+    pub fn read_raw_sampled<BUS>(&mut self, bus: &mut BUS) -> Result<Sample, A::Error>
+    where
+        A: VoltageSource<BUS>,
+    {
+        let V = self.front_end.read_voltage_sampled(bus)?;
+
+        let ORP = V.map(|V| orp_from_voltage(V, &self.cal_1, &self.cal_2));
+        self.last_meas = ORP.value;
+        Ok(ORP)
+    }
+
+    /// Convert a probe voltage to ORP, without using the Kalman filter.
+    pub(crate) fn orp_from_voltage(&mut self, V: f32) -> f32 {
+        let orp = orp_from_voltage(V, &self.cal_1, &self.cal_2);
 
         self.last_meas = orp;
         orp
     }
 
-    /// Useful for getting calibration data
-    pub fn read_voltage<I2C, E>(&mut self, i2c: &mut I2C) -> f32
+    /// Useful for getting calibration data. See `FrontEnd::read_voltage`.
+    pub fn read_voltage<BUS>(&mut self, bus: &mut BUS) -> Result<f32, A::Error>
     where
-        I2C: Write<Error = E> + WriteRead<Error = E>,
+        A: VoltageSource<BUS>,
     {
-        voltage_from_adc(take_reading(self.addr, PH_ORP_CMD, i2c))
+        self.front_end.read_voltage(bus)
     }
 
     /// Useful for getting calibration data
-    pub fn read_temp<I2C, E>(&mut self, i2c: &mut I2C) -> f32
+    pub fn read_temp<BUS>(&mut self, bus: &mut BUS) -> Result<f32, A::Error>
     where
-        I2C: Write<Error = E> + WriteRead<Error = E>,
+        A: VoltageSource<BUS>,
     {
-        temp_from_voltage(voltage_from_adc(take_reading(self.addr, T_CMD, i2c)))
+        self.front_end.read_temp(bus)
     }
 
-    /// Calibrate by measuring voltage at a given ORP. Set the
-    /// calibration, and return Voltage.
-    pub fn calibrate<I2C, E>(&mut self, ORP: f32, i2c: &mut I2C) -> f32
+    /// Calibrate by measuring voltage and temp in a standard solution. Set the
+    /// calibration, and return (Voltage, ORP). ORP sensors use at most 2 calibration
+    /// points, so `CalSlot::Three` is treated as `CalSlot::Two`.
+    pub fn calibrate<BUS, S>(
+        &mut self,
+        slot: CalSlot,
+        standard: OrpStandard,
+        mut t: S,
+        bus: &mut BUS,
+    ) -> Result<(f32, f32), Error<A::Error, S::Error>>
     where
-        I2C: Write<Error = E> + WriteRead<Error = E>,
+        A: VoltageSource<BUS>,
+        S: TemperatureSource<A, BUS>,
     {
-        let V = voltage_from_adc(take_reading(self.addr, PH_ORP_CMD, i2c));
-        self.cal = CalPtOrp::new(V, ORP);
-        V
+        let T = read_source(&mut t, self.front_end.board(bus))?;
+        let V = self.read_voltage(bus).map_err(Error::Bus)?;
+        let ORP = standard.orp(T, self.reference);
+        let pt = CalPtOrp::new(V, ORP);
+
+        match slot {
+            CalSlot::One => self.cal_1 = pt,
+            CalSlot::Two | CalSlot::Three => self.cal_2 = Some(pt),
+        }
+        Ok((V, ORP))
     }
 
-    pub fn calibrate_all(&mut self, pt: CalPtOrp) {
-        self.cal = pt;
+    pub fn calibrate_all(&mut self, pt0: CalPtOrp, pt1: Option<CalPtOrp>) {
+        self.cal_1 = pt0;
+        self.cal_2 = pt1;
     }
 
     pub fn reset_calibration(&mut self) {
-        self.cal = CalPtOrp::new(0.4, 400.);
+        self.cal_1 = CalPtOrp::new(0.4, 400.);
+        self.cal_2 = None;
+    }
+
+    /// Compare the calibration to an ideal sensor. A healthy sensor has a slope
+    /// near 1, and a small offset.
+    pub fn cal_quality(&self) -> OrpCalQuality {
+        let (a, b) = orp_cal_line(&self.cal_1, &self.cal_2);
+
+        OrpCalQuality {
+            slope: a / ORP_GAIN_IDEAL,
+            offset: b,
+        }
     }
 }
 
+/// Errors from reading a sensor that uses a temperature source.
+#[derive(Copy, Clone, Debug)]
+pub enum Error<E, TE> {
+    /// An error reading the ADC, eg on the I2C bus
+    Bus(E),
+    /// An error from the temperature source
+    Temp(TE),
+}
+
 /// We use SensorError on results from the `WaterMonitor` struct.
 #[derive(Copy, Clone, Debug)]
 pub enum SensorError {
@@ -470,6 +689,9 @@ pub enum SensorError {
 }
 
 #[derive(Debug, Clone)]
+/// Readings from each sensor. Errors from the `Ds18b20`, `Rtd` and `Thermistor`
+/// convert into `SensorError`, so any of them can fill `T`; eg
+/// `rtd.read(&mut spi, &mut delay).map_err(Into::into)`.
 pub struct Readings {
     pub T: Result<f32, SensorError>,
     pub pH: Result<f32, SensorError>,
@@ -477,14 +699,6 @@ pub struct Readings {
     pub ec: Result<f32, SensorError>,
 }
 
-/// Convert a 16-bit digital value to voltage.
-/// Input ranges from +- 2.048V; this is configurable.
-/// Output ranges from -32_768 to +32_767.
-pub fn voltage_from_adc(digi: i16) -> f32 {
-    let vref = 2.048;
-    (digi as f32 / 32_768.) * vref
-}
-
 /// Compute the result of a Lagrange polynomial of order 3.
 /// Algorithm created from the `P(x)` eq
 /// [here](https://mathworld.wolfram.com/LagrangeInterpolatingPolynomial.html).
@@ -538,13 +752,27 @@ fn ph_from_voltage(V: f32, T: f32, cal_0: &CalPt, cal_1: &CalPt, cal_2: &Option<
     }
 }
 
+/// Find the slope and offset of the line relating sensor voltage to ORP.
+/// With 2 calibration points we fit both; with 1, the line passes through (0., 0.).
+fn orp_cal_line(cal_0: &CalPtOrp, cal_1: &Option<CalPtOrp>) -> (f32, f32) {
+    match cal_1 {
+        // Points at the same voltage can't fit a slope; use the latest as a single point.
+        Some(c1) if c1.V == cal_0.V => (c1.ORP / c1.V, 0.),
+        Some(c1) => {
+            // a is the slope, ORP / V.
+            let a = (c1.ORP - cal_0.ORP) / (c1.V - cal_0.V);
+            let b = c1.ORP - a * c1.V;
+            (a, b)
+        }
+        None => (cal_0.ORP / cal_0.V, 0.),
+    }
+}
+
 /// Convert sensor voltage to ORP voltage
-/// We model the relationship between sensor voltage and ORP linearly
-/// between the calibration point, and (0., 0.). Output is in mV.
-fn orp_from_voltage(V: f32, cal: &CalPtOrp) -> f32 {
-    // a is the slope, ORP / V.
-    let a = cal.ORP / cal.V;
-    let b = cal.ORP - a * cal.V;
+/// We model the relationship between sensor voltage and ORP linearly, using
+/// 1 or 2 calibration points. Output is in mV.
+fn orp_from_voltage(V: f32, cal_0: &CalPtOrp, cal_1: &Option<CalPtOrp>) -> f32 {
+    let (a, b) = orp_cal_line(cal_0, cal_1);
     a * V + b
 }
 
@@ -555,29 +783,88 @@ pub fn temp_from_voltage(V: f32) -> f32 {
     100. * V - 60.
 }
 
-/// Take a measurement from an external ADC, using the I2C connection.
-fn take_reading<I2C, E>(addr: u8, cmd: u16, i2c: &mut I2C) -> i16
-where
-    I2C: Write<Error = E> + WriteRead<Error = E>,
-{
-    let mut result_buf: [u8; 2] = [0, 0];
+/// Map voltage to temperature for the onboard sensor, in °C, correcting the
+/// datasheet transfer function with up to 2 calibration points. With 1 point, we
+/// correct offset; with 2, we fit a line through both.
+fn temp_from_voltage_cal(
+    V: f32,
+    model: TempSensorModel,
+    cal_0: &Option<CalPtT>,
+    cal_1: &Option<CalPtT>,
+) -> f32 {
+    match (cal_0, cal_1) {
+        // Points at the same voltage can't fit a slope; use the latest as a single point.
+        (Some(c0), Some(c1)) if c0.V == c1.V => model.temp(V) + c1.T - model.temp(c1.V),
+        (Some(c0), Some(c1)) => {
+            // a is the slope, T / V.
+            let a = (c1.T - c0.T) / (c1.V - c0.V);
+            let b = c1.T - a * c1.V;
+            a * V + b
+        }
+        (Some(c), None) | (None, Some(c)) => model.temp(V) + c.T - model.temp(c.V),
+        (None, None) => model.temp(V),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temp_one_point() {
+        // The LM61 reads 25°C at 0.85V; this one reads 0.5°C high.
+        let cal = Some(CalPtT::new(0.85, 24.5));
+
+        for &(cal_0, cal_1) in &[(cal, None), (None, cal)] {
+            let T = temp_from_voltage_cal(0.9, TempSensorModel::Lm61, &cal_0, &cal_1);
+            assert!((T - 29.5).abs() < 1e-4, "{}", T);
+        }
+    }
 
-    // Set up the cfg, and command a one-shot reading. Note that we
-    // pass the 16-bit i2c command as 2 bytes.
-    i2c.write(addr, &[CFG_REG, (cmd >> 8) as u8, cmd as u8])
-        .ok();
+    #[test]
+    fn temp_two_point() {
+        let cal_0 = Some(CalPtT::new(0.7, 10.));
+        let cal_1 = Some(CalPtT::new(0.9, 30.5));
 
-    // Wait until the conversion is complete.
-    let mut converting = true;
-    let mut buf = [0, 0];
-    while converting {
-        i2c.write_read(addr, &[CFG_REG], &mut buf).ok();
-        // First of 16 cfg reg bits is 0 while converting, 1 when ready. (when reading)
-        converting = buf[0] >> 7 == 0;
+        // 102.5°C / V, through (0.7V, 10°C).
+        let T = temp_from_voltage_cal(0.8, TempSensorModel::Lm61, &cal_0, &cal_1);
+        assert!((T - 20.25).abs() < 1e-3, "{}", T);
     }
 
-    // Read the result from the conversion register.
-    i2c.write_read(addr, &[CONV_REG], &mut result_buf).ok();
+    #[test]
+    fn temp_points_at_same_voltage() {
+        let cal_0 = Some(CalPtT::new(0.85, 24.));
+        let cal_1 = Some(CalPtT::new(0.85, 24.5));
 
-    i16::from_be_bytes([result_buf[0], result_buf[1]])
+        let T = temp_from_voltage_cal(0.9, TempSensorModel::Lm61, &cal_0, &cal_1);
+        assert!((T - 29.5).abs() < 1e-4, "{}", T);
+    }
+
+    #[test]
+    fn orp_two_point() {
+        let cal_0 = CalPtOrp::new(0.2, 200.);
+        let cal_1 = Some(CalPtOrp::new(0.45, 470.));
+
+        let (a, b) = orp_cal_line(&cal_0, &cal_1);
+        assert!((a - 1_080.).abs() < 0.01);
+        assert!((b + 16.).abs() < 0.01);
+        assert!((orp_from_voltage(0.3, &cal_0, &cal_1) - 308.).abs() < 0.01);
+
+        let mut sensor = OrpSensor::new(1.);
+        sensor.calibrate_all(cal_0, cal_1);
+        let quality = sensor.cal_quality();
+        assert!((quality.slope - 1.08).abs() < 1e-4);
+        assert!((quality.offset + 16.).abs() < 0.01);
+    }
+
+    #[test]
+    fn orp_points_at_same_voltage() {
+        let cal_0 = CalPtOrp::new(0.3, 300.);
+        let cal_1 = Some(CalPtOrp::new(0.3, 310.));
+
+        let (a, b) = orp_cal_line(&cal_0, &cal_1);
+        assert!(a.is_finite());
+        assert_eq!(b, 0.);
+        assert!((orp_from_voltage(0.3, &cal_0, &cal_1) - 310.).abs() < 0.01);
+    }
 }
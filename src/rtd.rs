@@ -1,8 +1,7 @@
 //! Supports the Max31865. Based on [rudihorn's max31865 lib](https://github.com/rudihorn/max31865),
 //! with modifications like support for Pt1000, and borrowing SPI instead of owning the bus.
 
-use core::marker::Unsize;
-use core::mem;
+use core::{convert::Infallible, fmt};
 
 use embedded_hal::{
     blocking::{
@@ -12,6 +11,23 @@ use embedded_hal::{
     digital::v2::{InputPin, OutputPin},
 };
 
+#[allow(unused_imports)]
+use num_traits::float::Float; // Required for `sqrt` and `powi` in `no_std`.
+
+use crate::{Board, CalSlot, SensorError, TemperatureSource};
+
+// Stop refining temperatures below 0°C once steps are smaller than this, in °C.
+const CVD_TOLERANCE: f32 = 0.001;
+const CVD_MAX_ITERS: u8 = 10;
+
+// Enable Vbias, and wait this long for the input RC network to settle before converting.
+const VBIAS_SETTLE_MS: u8 = 60;
+
+// Wait between steps of manual fault detection, for the input RC network to settle.
+const FAULT_SETTLE_MS: u8 = 10;
+// Fault detection completes in well under this many 1ms polls.
+const FAULT_MAX_POLLS: u8 = 10;
+
 #[allow(non_camel_case_types)]
 #[allow(dead_code)]
 #[repr(u8)]
@@ -36,7 +52,7 @@ enum Register {
 // See Table2. Configuration Register Definition for info on these enums.
 
 #[repr(u8)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 /// vbias voltage; must be On to perform conversion.
 pub enum Vbias {
     Off = 0,
@@ -44,7 +60,7 @@ pub enum Vbias {
 }
 
 #[repr(u8)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 /// Set `Auto` to automatically perform conversion.
 pub enum ConversionMode {
     NormallyOff = 0,
@@ -52,7 +68,7 @@ pub enum ConversionMode {
 }
 
 #[repr(u8)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 /// RM: When the conversion mode is set to “Normally Off”, write 1  to  this  bit
 /// to  start  a  conversion.  This  causes  a  single  resistance  conversion
 /// to  take  place.  The  conversion  is  triggered  when  CS  goes  high  after
@@ -62,7 +78,17 @@ pub enum OneShot {
     OneShot = 1, // auto-clear
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Fault detection cycle to run, set by config bits D3:D2. See Table 3.
+pub enum FaultCycle {
+    /// The chip times the cycle itself. Use when the input filter's time constant is
+    /// under 100us.
+    Automatic,
+    /// We time the cycle, allowing for a larger input filter.
+    Manual,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 /// Thinly wraps `max31865::SensorType`.
 pub enum Wires {
     Two,
@@ -71,59 +97,419 @@ pub enum Wires {
 }
 
 #[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 /// Mains freq to filter out, eg 50Hz in Europe, 50Hz in US.
 pub enum FilterMode {
     Filter60Hz = 0,
     Filter50Hz = 1,
 }
 
+impl FilterMode {
+    /// Time a single conversion takes in this mode, in ms: ~52ms at 60Hz, and
+    /// 62.5ms at 50Hz. Rounded up.
+    pub fn conversion_time(&self) -> u8 {
+        match self {
+            Self::Filter60Hz => 53,
+            Self::Filter50Hz => 63,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The MAX31865's configuration register. See Table 2. (`RtdConfig` describes the
+/// circuit instead.)
+pub struct Config {
+    pub vbias: Vbias,
+    pub conversion_mode: ConversionMode,
+    pub one_shot: OneShot,
+    pub wires: Wires,
+    /// The fault detection cycle in progress, if any. Writing `Some` starts one.
+    pub fault_cycle: Option<FaultCycle>,
+    /// Write `true` to clear the fault status. Always reads `false`.
+    pub fault_clear: bool,
+    pub filter_mode: FilterMode,
+}
+
+impl Config {
+    /// Decode the register. The chip doesn't distinguish 2 and 4-wire RTDs, so when the
+    /// 3-wire bit is clear, `wires` picks between them: 4-wire if `wires` is 4, and
+    /// 2-wire otherwise.
+    pub fn from_byte(val: u8, wires: Wires) -> Self {
+        Self {
+            vbias: if val & (1 << 7) > 0 {
+                Vbias::On
+            } else {
+                Vbias::Off
+            },
+            conversion_mode: if val & (1 << 6) > 0 {
+                ConversionMode::Auto
+            } else {
+                ConversionMode::NormallyOff
+            },
+            one_shot: if val & (1 << 5) > 0 {
+                OneShot::OneShot
+            } else {
+                OneShot::Cleared
+            },
+            wires: match (val & (1 << 4) > 0, wires) {
+                (true, _) => Wires::Three,
+                (false, Wires::Four) => Wires::Four,
+                (false, _) => Wires::Two,
+            },
+            fault_cycle: match (val >> 2) & 0b11 {
+                0b00 => None,
+                0b01 => Some(FaultCycle::Automatic),
+                _ => Some(FaultCycle::Manual),
+            },
+            fault_clear: val & (1 << 1) > 0,
+            filter_mode: if val & 1 > 0 {
+                FilterMode::Filter50Hz
+            } else {
+                FilterMode::Filter60Hz
+            },
+        }
+    }
+
+    /// Encode the register. A manual fault cycle is encoded as its first step.
+    pub fn to_byte(&self) -> u8 {
+        let wires = match self.wires {
+            Wires::Two => 0,
+            Wires::Three => 1,
+            Wires::Four => 0,
+        };
+
+        let fault_cycle = match self.fault_cycle {
+            None => 0b00,
+            Some(FaultCycle::Automatic) => 0b01,
+            Some(FaultCycle::Manual) => 0b10,
+        };
+
+        ((self.vbias as u8) << 7)
+            | ((self.conversion_mode as u8) << 6)
+            | ((self.one_shot as u8) << 5)
+            | (wires << 4)
+            | (fault_cycle << 2)
+            | ((self.fault_clear as u8) << 1)
+            | (self.filter_mode as u8)
+    }
+}
+
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Vbias: {:?}, conversion: {:?}, one-shot: {:?}, wires: {:?}, fault cycle: {:?}, \
+            filter: {:?}",
+            self.vbias,
+            self.conversion_mode,
+            self.one_shot,
+            self.wires,
+            self.fault_cycle,
+            self.filter_mode
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// The MAX31865's fault status register. See Table 7.
+pub struct FaultStatus {
+    /// RTD resistance above the high fault threshold.
+    pub rtd_high: bool,
+    /// RTD resistance below the low fault threshold.
+    pub rtd_low: bool,
+    /// REFIN- > 0.85 x Vbias.
+    pub refin_high: bool,
+    /// REFIN- < 0.85 x Vbias, with FORCE- open.
+    pub refin_low: bool,
+    /// RTDIN- < 0.85 x Vbias, with FORCE- open.
+    pub rtdin_low: bool,
+    /// Over or under voltage on an input.
+    pub overvoltage: bool,
+}
+
+impl FaultStatus {
+    pub fn from_byte(val: u8) -> Self {
+        Self {
+            rtd_high: val & (1 << 7) > 0,
+            rtd_low: val & (1 << 6) > 0,
+            refin_high: val & (1 << 5) > 0,
+            refin_low: val & (1 << 4) > 0,
+            rtdin_low: val & (1 << 3) > 0,
+            overvoltage: val & (1 << 2) > 0,
+        }
+    }
+
+    /// Returns `true` if any fault is set.
+    pub fn any(&self) -> bool {
+        *self != Self::default()
+    }
+}
+
+impl fmt::Display for FaultStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.any() {
+            return write!(f, "No faults");
+        }
+
+        let faults = [
+            (self.rtd_high, "RTD above high threshold"),
+            (self.rtd_low, "RTD below low threshold"),
+            (self.refin_high, "REFIN- > 0.85 x Vbias"),
+            (self.refin_low, "REFIN- < 0.85 x Vbias (FORCE- open)"),
+            (self.rtdin_low, "RTDIN- < 0.85 x Vbias (FORCE- open)"),
+            (self.overvoltage, "Over/undervoltage"),
+        ];
+
+        let mut first = true;
+        for (_, desc) in faults.iter().filter(|(set, _)| *set) {
+            if !first {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", desc)?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
+/// The platinum RTD element. For platinum elements without a preset, eg Pt200, use
+/// `Custom` with the nominal resistance. Nickel and copper elements, eg Ni120, aren't
+/// supported: their curves don't fit the Callendar–Van Dusen equation.
 pub enum RtdType {
     Pt100,
+    Pt500,
     Pt1000,
+    /// An element with the given nominal resistance at 0°C, in Ohms.
+    Custom(f32),
+}
+
+impl RtdType {
+    /// Nominal resistance at 0°C (R0), in Ohms.
+    pub fn r0(&self) -> f32 {
+        match self {
+            Self::Pt100 => 100.,
+            Self::Pt500 => 500.,
+            Self::Pt1000 => 1_000.,
+            Self::Custom(r0) => *r0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// Callendar–Van Dusen coefficients, relating a platinum RTD's resistance to
+/// temperature: `R(T) = R0 * (1 + A*T + B*T^2 + C*(T - 100)*T^3)`, where `C` only
+/// applies below 0°C.
+pub struct CvdCoeffs {
+    pub A: f32,
+    pub B: f32,
+    pub C: f32,
+}
+
+impl CvdCoeffs {
+    /// Coefficients for standard platinum RTDs (α = 0.00385), from IEC 60751.
+    pub const IEC_60751: Self = Self {
+        A: 3.9083e-3,
+        B: -5.775e-7,
+        C: -4.183e-12,
+    };
+
+    pub fn new(A: f32, B: f32, C: f32) -> Self {
+        Self { A, B, C }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// Errors from the RTD.
+pub enum RtdError<E, PE, DE = Infallible> {
+    /// An SPI bus error.
+    Spi(E),
+    /// An error setting the chip-select pin.
+    Pin(PE),
+    /// An error reading the DRDY pin.
+    Drdy(DE),
+    /// The chip flagged a fault during conversion, eg from a disconnected probe.
+    /// The fault status is cleared after reading it.
+    Fault(FaultStatus),
+    /// DRDY didn't assert within the conversion time, so the RTD registers may hold a
+    /// stale reading.
+    Timeout,
+    /// The chip's 3-wire setting doesn't match the driver's `Wires`; eg after the chip
+    /// lost power, and reset. Holds the configuration read.
+    WiresMismatch(Config),
+}
+
+impl<E, PE, DE> From<RtdError<E, PE, DE>> for SensorError {
+    fn from(e: RtdError<E, PE, DE>) -> Self {
+        match e {
+            RtdError::Spi(_) | RtdError::Pin(_) | RtdError::Drdy(_) => Self::Bus,
+            RtdError::Fault(_) => Self::BadMeasurement,
+            RtdError::Timeout => Self::NotConnected,
+            RtdError::WiresMismatch(_) => Self::BadMeasurement,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// Describes the RTD, and the circuit it's connected to.
+pub struct RtdConfig {
+    pub type_: RtdType,
+    pub wires: Wires,
+    /// The reference resistor, in Ohms. The MAX31865 measures the RTD's resistance
+    /// as a ratio of this.
+    pub ref_resistance: f32,
+    /// `NormallyOff` performs a one-shot conversion on each read, with Vbias only
+    /// on during it. `Auto` converts continuously, with Vbias always on; reads
+    /// return the latest conversion. This draws a few mA more.
+    pub conversion_mode: ConversionMode,
+    pub filter_mode: FilterMode,
+}
+
+impl RtdConfig {
+    /// Create a config with a reference resistor of 3x the RTD's nominal
+    /// resistance, as on AnyLeaf boards; eg 300Ω for a PT100. Uses one-shot
+    /// conversions, filtering 60Hz.
+    pub fn new(type_: RtdType, wires: Wires) -> Self {
+        Self {
+            type_,
+            wires,
+            ref_resistance: 3. * type_.r0(),
+            conversion_mode: ConversionMode::NormallyOff,
+            filter_mode: FilterMode::Filter60Hz,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// Data for a single RTD calibration point.
+pub struct CalPtRtd {
+    pub raw: u16, // Raw RTD register value, as returned by `Rtd::read_raw`.
+    pub T: f32,   // in Celsius
+}
+
+impl CalPtRtd {
+    pub fn new(raw: u16, T: f32) -> Self {
+        Self { raw, T }
+    }
+}
+
+/// Used in place of a DRDY pin, when it's not connected. See `Rtd::new_with_drdy`.
+pub struct NoDrdy;
+
+impl InputPin for NoDrdy {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Infallible> {
+        Ok(true)
+    }
+
+    fn is_low(&self) -> Result<bool, Infallible> {
+        Ok(false)
+    }
 }
 
-/// A struct used to describe the RTD. Owns the cs pin.
-pub struct Rtd<CS: OutputPin> {
+/// A struct used to describe the RTD. Owns the cs pin, and DRDY pin if used.
+pub struct Rtd<CS: OutputPin, RDY: InputPin = NoDrdy> {
     cs: CS,
-    calibration: u32,
+    drdy: Option<RDY>,
+    conversion_mode: ConversionMode,
+    filter_mode: FilterMode,
+    calibration: f32,     // The effective reference resistance, in Ohms.
+    offset: f32,          // Ohms. Corrects the element's R0 error, from 2-pt calibration.
+    lead_resistance: f32, // Ohms. Total for both leads, of 2-wire RTDs.
+    ref_resistance: f32,
     type_: RtdType,
     wires: Wires,
+    coeffs: CvdCoeffs,
+    cal_1: Option<CalPtRtd>,
+    cal_2: Option<CalPtRtd>,
 }
 
 impl<CS: OutputPin> Rtd<CS> {
-    pub fn new<SPI, E>(spi: &mut SPI, mut cs: CS, type_: RtdType, wires: Wires) -> Self
+    /// Create a new RTD, with AnyLeaf's reference resistors. See `RtdConfig::new`.
+    pub fn new<SPI, E>(
+        spi: &mut SPI,
+        cs: CS,
+        type_: RtdType,
+        wires: Wires,
+    ) -> Result<Self, RtdError<E, CS::Error>>
     where
         SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
     {
-        cs.set_high().ok();
+        Self::new_with_config(spi, cs, RtdConfig::new(type_, wires))
+    }
 
-        // todo: This assumes specific hardware ref resistors
-        let ref_R = match type_ {
-            RtdType::Pt100 => 300,
-            RtdType::Pt1000 => 3_000,
-        };
+    /// Create a new RTD, on a circuit described by `config`.
+    pub fn new_with_config<SPI, E>(
+        spi: &mut SPI,
+        cs: CS,
+        config: RtdConfig,
+    ) -> Result<Self, RtdError<E, CS::Error>>
+    where
+        SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
+    {
+        Self::init(spi, cs, None, config)
+    }
+}
+
+impl<CS: OutputPin, RDY: InputPin> Rtd<CS, RDY> {
+    /// Create a new RTD, with its DRDY pin connected. Reads wait for DRDY to go low
+    /// instead of for a fixed conversion time.
+    pub fn new_with_drdy<SPI, E>(
+        spi: &mut SPI,
+        cs: CS,
+        drdy: RDY,
+        config: RtdConfig,
+    ) -> Result<Self, RtdError<E, CS::Error, RDY::Error>>
+    where
+        SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
+    {
+        Self::init(spi, cs, Some(drdy), config)
+    }
+
+    fn init<SPI, E>(
+        spi: &mut SPI,
+        mut cs: CS,
+        drdy: Option<RDY>,
+        config: RtdConfig,
+    ) -> Result<Self, RtdError<E, CS::Error, RDY::Error>>
+    where
+        SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
+    {
+        cs.set_high().map_err(RtdError::Pin)?;
 
         let mut result = Self {
             cs,
-            // Set cal to the circuit's reference resistance * 100.
-            calibration: ref_R * 100,
-            type_,
-            wires,
+            drdy,
+            conversion_mode: config.conversion_mode,
+            filter_mode: config.filter_mode,
+            calibration: config.ref_resistance,
+            offset: 0.,
+            lead_resistance: 0.,
+            ref_resistance: config.ref_resistance,
+            type_: config.type_,
+            wires: config.wires,
+            coeffs: CvdCoeffs::IEC_60751,
+            cal_1: None,
+            cal_2: None,
         };
 
-        // Set up with vbias off, and in one-shot mode, to save power.
-        result
-            .configure(
-                spi,
-                Vbias::Off,
-                ConversionMode::NormallyOff,
-                OneShot::Cleared,
-                FilterMode::Filter60Hz,
-            )
-            .ok();
+        // In one-shot mode, set up with vbias off to save power. Continuous conversion
+        // requires it on.
+        let vbias = match config.conversion_mode {
+            ConversionMode::NormallyOff => Vbias::Off,
+            ConversionMode::Auto => Vbias::On,
+        };
 
-        result
+        result.configure(
+            spi,
+            vbias,
+            config.conversion_mode,
+            OneShot::Cleared,
+            config.filter_mode,
+        )?;
+
+        Ok(result)
     }
 
     // /// Appears to be required after a power cycle, or it will read 0.
@@ -164,37 +550,58 @@ impl<CS: OutputPin> Rtd<CS> {
         conversion_mode: ConversionMode,
         one_shot: OneShot,
         filter_mode: FilterMode,
-    ) -> Result<(), E>
+    ) -> Result<(), RtdError<E, CS::Error, RDY::Error>>
     where
         SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
     {
-        let wires = match self.wires {
-            Wires::Two => 0,
-            Wires::Three => 1,
-            Wires::Four => 0,
+        let config = Config {
+            vbias,
+            conversion_mode,
+            one_shot,
+            wires: self.wires,
+            fault_cycle: None,
+            fault_clear: false,
+            filter_mode,
         };
 
-        let conf: u8 = ((vbias as u8) << 7)
-            | ((conversion_mode as u8) << 6)
-            | ((one_shot as u8) << 5)
-            | (wires << 4)
-            | (filter_mode as u8);
+        self.write_config(spi, &config)
+    }
 
-        self.write(spi, Register::ConfigW, conf)?;
-        Ok(())
+    /// Write the configuration register, eg with a modified `Config` from
+    /// `read_config`.
+    pub fn write_config<SPI, E>(
+        &mut self,
+        spi: &mut SPI,
+        config: &Config,
+    ) -> Result<(), RtdError<E, CS::Error, RDY::Error>>
+    where
+        SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
+    {
+        self.wires = config.wires;
+        self.conversion_mode = config.conversion_mode;
+        self.filter_mode = config.filter_mode;
+        self.write(spi, Register::ConfigW, config.to_byte())
     }
 
-    fn write<SPI, E>(&mut self, spi: &mut SPI, reg: Register, val: u8) -> Result<(), E>
+    fn write<SPI, E>(
+        &mut self,
+        spi: &mut SPI,
+        reg: Register,
+        val: u8,
+    ) -> Result<(), RtdError<E, CS::Error, RDY::Error>>
     where
         SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
     {
-        self.cs.set_low().ok();
-        spi.write(&[reg as u8, val])?;
-        self.cs.set_high().ok();
-        Ok(())
+        self.cs.set_low().map_err(RtdError::Pin)?;
+        spi.write(&[reg as u8, val]).map_err(RtdError::Spi)?;
+        self.cs.set_high().map_err(RtdError::Pin)
     }
 
-    fn read_data<SPI, E>(&mut self, spi: &mut SPI, reg: Register) -> Result<u8, E>
+    fn read_data<SPI, E>(
+        &mut self,
+        spi: &mut SPI,
+        reg: Register,
+    ) -> Result<u8, RtdError<E, CS::Error, RDY::Error>>
     where
         SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
     {
@@ -202,19 +609,22 @@ impl<CS: OutputPin> Rtd<CS> {
         Ok(buffer[1])
     }
 
-    fn read_many<B, SPI, E>(&mut self, spi: &mut SPI, reg: Register) -> Result<B, E>
+    /// Read `N - 1` bytes, starting at `reg`. The first byte of the result is
+    /// clocked out while sending the address.
+    fn read_many<SPI, E, const N: usize>(
+        &mut self,
+        spi: &mut SPI,
+        reg: Register,
+    ) -> Result<[u8; N], RtdError<E, CS::Error, RDY::Error>>
     where
-        B: Unsize<[u8]>,
         SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
     {
-        let mut buffer: B = unsafe { mem::zeroed() };
-        {
-            let slice: &mut [u8] = &mut buffer;
-            slice[0] = reg as u8;
-            self.cs.set_low().ok();
-            spi.transfer(slice)?;
-            self.cs.set_high().ok();
-        }
+        let mut buffer = [0; N];
+        buffer[0] = reg as u8;
+
+        self.cs.set_low().map_err(RtdError::Pin)?;
+        spi.transfer(&mut buffer).map_err(RtdError::Spi)?;
+        self.cs.set_high().map_err(RtdError::Pin)?;
 
         Ok(buffer)
     }
@@ -228,14 +638,24 @@ impl<CS: OutputPin> Rtd<CS> {
     /// resistor (i.e. 2^15 - 1 would be the exact same resistance as the reference
     /// resistor). See manual for further information.
     /// The last bit specifies if the conversion was successful.
+    ///
+    /// In continuous conversion mode, this returns the latest conversion; if DRDY is
+    /// connected, it first waits for a new one.
     pub fn read_raw<SPI, E, D: DelayMs<u8>>(
         &mut self,
         spi: &mut SPI,
         delay: &mut D,
-    ) -> Result<u16, E>
+    ) -> Result<u16, RtdError<E, CS::Error, RDY::Error>>
     where
         SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
     {
+        if let ConversionMode::Auto = self.conversion_mode {
+            if self.drdy.is_some() {
+                self.wait_conversion(delay)?;
+            }
+            return self.read_rtd_registers(spi);
+        }
+
         // Set up a one-shot conversion by enabling Vbias and OneShot.
         // See the `1-Shot (D5)` section of the datasheet for details.
         let existing_config = self.read_data(spi, Register::Config)?;
@@ -250,20 +670,34 @@ impl<CS: OutputPin> Rtd<CS> {
         // mode or 62.5ms in 50Hz filter mode to complete. 1-Shot
         // is a self-clearing bit.
         // todo: Is this wasting power on the WM?
-        delay.delay_ms(60_u8);
+        delay.delay_ms(VBIAS_SETTLE_MS);
 
         // `When the conversion mode is set to “Normally Off”, write
         // 1 to this bit to start a conversion.`
         // Trigger a one-shot conversion.
         self.write(spi, Register::ConfigW, conf | (1 << 5))?;
+        self.wait_conversion(delay)?;
 
-        let msb = self.read_data(spi, Register::RtdMsb)? as u16;
-        let lsb = self.read_data(spi, Register::RtdLsb)? as u16;
+        let raw = self.read_rtd_registers(spi)?;
 
         // Turn off Vbias by writing the original config.
         self.write(spi, Register::ConfigW, existing_config)?;
 
-        Ok((msb << 8) | lsb)
+        Ok(raw)
+    }
+
+    fn read_rtd_registers<SPI, E>(
+        &mut self,
+        spi: &mut SPI,
+    ) -> Result<u16, RtdError<E, CS::Error, RDY::Error>>
+    where
+        SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
+    {
+        // Read MSB and LSB in one transaction, so they're from the same conversion in
+        // continuous mode.
+        let buffer: [u8; 3] = self.read_many(spi, Register::RtdMsb)?;
+
+        Ok(u16::from_be_bytes([buffer[1], buffer[2]]))
     }
 
     /// Measure RTD resistance, in Ohms.
@@ -271,181 +705,636 @@ impl<CS: OutputPin> Rtd<CS> {
         &mut self,
         spi: &mut SPI,
         delay: &mut D,
-    ) -> Result<f32, E>
+    ) -> Result<f32, RtdError<E, CS::Error, RDY::Error>>
     where
         SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
     {
         let raw = self.read_raw(spi, delay)?;
+        self.check_fault(spi, raw)?;
 
-        Ok((((raw >> 1) as u32 * self.calibration) >> 15) as f32)
+        Ok(self.resistance_from_raw(raw) - self.lead_resistance)
+    }
+
+    /// Convert a raw reading to resistance in Ohms, including that of the leads.
+    fn resistance_from_raw(&self, raw: u16) -> f32 {
+        // The top 15 bits are the ratio of RTD to reference resistance. Keep the
+        // fractional Ohms: 1 LSB is ~0.01Ω for a PT100 with a 400Ω reference.
+        let ratio = (raw >> 1) as f32 / 32_768.;
+        ratio * self.calibration + self.offset
+    }
+
+    /// If the fault bit of a raw reading is set, clear the fault, and return it as an
+    /// error.
+    fn check_fault<SPI, E>(
+        &mut self,
+        spi: &mut SPI,
+        raw: u16,
+    ) -> Result<(), RtdError<E, CS::Error, RDY::Error>>
+    where
+        SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
+    {
+        if raw & 1 == 0 {
+            return Ok(());
+        }
+
+        let status = self.fault_status(spi)?;
+        self.clear_faults(spi)?;
+
+        Err(RtdError::Fault(status))
     }
 
     /// Measure temperature, in Celsius
-    pub fn read<SPI, E, D: DelayMs<u8>>(&mut self, spi: &mut SPI, delay: &mut D) -> Result<f32, E>
+    pub fn read<SPI, E, D: DelayMs<u8>>(
+        &mut self,
+        spi: &mut SPI,
+        delay: &mut D,
+    ) -> Result<f32, RtdError<E, CS::Error, RDY::Error>>
     where
         SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
     {
         let resistance = self.read_resistance(spi, delay)?;
-        let temp = lookup_temperature(resistance as u16, self.type_);
 
-        Ok(temp as f32 / 100.)
+        Ok(temp_from_resistance(
+            resistance,
+            self.type_.r0(),
+            &self.coeffs,
+        ))
+    }
+
+    /// Set the Callendar–Van Dusen coefficients used to convert resistance to
+    /// temperature. Defaults to `CvdCoeffs::IEC_60751`; change this for platinum
+    /// elements with a different α, eg 0.003926.
+    pub fn set_coeffs(&mut self, coeffs: CvdCoeffs) {
+        self.coeffs = coeffs;
+    }
+
+    pub fn coeffs(&self) -> CvdCoeffs {
+        self.coeffs
+    }
+
+    /// Borrow this RTD with its SPI bus and delay, so it can be used as a temperature
+    /// source, eg for pH compensation.
+    pub fn source<'a, SPI, D>(
+        &'a mut self,
+        spi: &'a mut SPI,
+        delay: &'a mut D,
+    ) -> RtdSource<'a, CS, RDY, SPI, D> {
+        RtdSource {
+            rtd: self,
+            spi,
+            delay,
+        }
+    }
+
+    /// Set fault thresholds, in Ohms. The chip flags a fault when the RTD's resistance
+    /// is above `high`, or below `low`, eg from open or shorted leads. Defaults to the
+    /// full range.
+    pub fn set_fault_thresholds<SPI, E>(
+        &mut self,
+        spi: &mut SPI,
+        low: f32,
+        high: f32,
+    ) -> Result<(), RtdError<E, CS::Error, RDY::Error>>
+    where
+        SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
+    {
+        let low = self.raw_from_resistance(low).to_be_bytes();
+        let high = self.raw_from_resistance(high).to_be_bytes();
+
+        self.write(spi, Register::LowFaultThresholdMsbW, low[0])?;
+        self.write(spi, Register::LowFaultThresholdLsbW, low[1])?;
+        self.write(spi, Register::HighFaultThresholdMsbW, high[0])?;
+        self.write(spi, Register::HighFaultThresholdLsbW, high[1])
     }
 
-    /// Return the configuration register data.
-    pub fn read_config<SPI, E>(&mut self, spi: &mut SPI) -> Result<[bool; 8], E>
+    /// Set fault thresholds, in °C. See `set_fault_thresholds`.
+    pub fn set_fault_thresholds_temp<SPI, E>(
+        &mut self,
+        spi: &mut SPI,
+        low: f32,
+        high: f32,
+    ) -> Result<(), RtdError<E, CS::Error, RDY::Error>>
     where
         SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
     {
-        let status = self.read_data(spi, Register::Config)?;
-
-        let vbias = status & (1 << 7) > 0;
-        let conv_mode = status & (1 << 6) > 0;
-        let one_shot = status & (1 << 5) > 0;
-        let wire = status & (1 << 4) > 0;
-        let fault_deta = status & (1 << 3) > 0;
-        let fault_detb = status & (1 << 2) > 0;
-        let fault_status = status & (1 << 1) > 0;
-        let filter = status & (1 << 0) > 0;
-
-        Ok([
-            vbias,
-            conv_mode,
-            one_shot,
-            wire,
-            fault_deta,
-            fault_detb,
-            fault_status,
-            filter,
-        ])
+        let R0 = self.type_.r0();
+        self.set_fault_thresholds(
+            spi,
+            resistance_from_temp(low, R0, &self.coeffs),
+            resistance_from_temp(high, R0, &self.coeffs),
+        )
     }
 
-    /// Find the fault status. See Table 7.
-    pub fn fault_status<SPI, E>(&mut self, spi: &mut SPI) -> Result<[bool; 6], E>
+    /// Convert resistance in Ohms to the RTD register format, using our calibration:
+    /// a 15-bit ratio to the reference resistance, shifted left 1.
+    fn raw_from_resistance(&self, R: f32) -> u16 {
+        let ratio = (R + self.lead_resistance - self.offset) * 32_768. / self.calibration;
+
+        if ratio <= 0. {
+            0
+        } else if ratio >= 32_767. {
+            0xffff
+        } else {
+            (ratio as u16) << 1
+        }
+    }
+
+    /// Run the chip's fault detection cycle, which checks for open or shorted RTD and
+    /// reference connections, and return the fault status. See Table 3.
+    pub fn detect_faults<SPI, E, D: DelayMs<u8>>(
+        &mut self,
+        spi: &mut SPI,
+        delay: &mut D,
+        cycle: FaultCycle,
+    ) -> Result<FaultStatus, RtdError<E, CS::Error, RDY::Error>>
     where
         SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
     {
-        let status = self.read_data(spi, Register::FaultStatus)?;
+        let existing_config = self.read_data(spi, Register::Config)?;
+
+        // Keep the wire and filter bits; clear the rest, and enable Vbias.
+        let conf = (existing_config & 0b0001_0001) | (1 << 7);
 
-        let rtd_high = status & (1 << 7) > 0;
-        let rtd_low = status & (1 << 6) > 0;
-        let refin1 = status & (1 << 5) > 0;
-        let refin2 = status & (1 << 4) > 0;
-        let rtdin = status & (1 << 3) > 0;
-        let overv = status & (1 << 2) > 0;
+        match cycle {
+            // `Write 100X010X`
+            FaultCycle::Automatic => self.write(spi, Register::ConfigW, conf | 0b0100)?,
+            // `Write 100X100X. Wait at least 5 time constants. Write 100X110X.`
+            FaultCycle::Manual => {
+                self.write(spi, Register::ConfigW, conf | 0b1000)?;
+                delay.delay_ms(FAULT_SETTLE_MS);
+                self.write(spi, Register::ConfigW, conf | 0b1100)?;
+            }
+        }
+
+        // D3:D2 read 00 when the cycle is complete.
+        for _ in 0..FAULT_MAX_POLLS {
+            delay.delay_ms(1);
+            if self.read_data(spi, Register::Config)? & 0b1100 == 0 {
+                break;
+            }
+        }
+
+        let status = self.fault_status(spi)?;
+
+        self.write(spi, Register::ConfigW, existing_config)?;
 
-        Ok([rtd_high, rtd_low, refin1, refin2, rtdin, overv])
+        Ok(status)
+    }
+
+    /// Clear the fault status register.
+    pub fn clear_faults<SPI, E>(
+        &mut self,
+        spi: &mut SPI,
+    ) -> Result<(), RtdError<E, CS::Error, RDY::Error>>
+    where
+        SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
+    {
+        let existing_config = self.read_data(spi, Register::Config)?;
+
+        // `Write a 1 to this bit while writing 0 to bits D5, D3, and D2`
+        let conf = (existing_config & !0b0010_1100) | (1 << 1);
+        self.write(spi, Register::ConfigW, conf)
+    }
+
+    /// Return the configuration register data. Returns `RtdError::WiresMismatch` if the
+    /// chip's 3-wire setting doesn't match the driver's.
+    pub fn read_config<SPI, E>(
+        &mut self,
+        spi: &mut SPI,
+    ) -> Result<Config, RtdError<E, CS::Error, RDY::Error>>
+    where
+        SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
+    {
+        let val = self.read_data(spi, Register::Config)?;
+        let config = Config::from_byte(val, self.wires);
+
+        if (config.wires == Wires::Three) != (self.wires == Wires::Three) {
+            return Err(RtdError::WiresMismatch(config));
+        }
+        Ok(config)
+    }
+
+    /// Find the fault status. See Table 7.
+    pub fn fault_status<SPI, E>(
+        &mut self,
+        spi: &mut SPI,
+    ) -> Result<FaultStatus, RtdError<E, CS::Error, RDY::Error>>
+    where
+        SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
+    {
+        let val = self.read_data(spi, Register::FaultStatus)?;
+        Ok(FaultStatus::from_byte(val))
     }
 
-    /// todo: Sort this out.
-    ///
     /// Set the calibration reference resistance.
     /// This can be used to calibrate inaccuracies of both the reference resistor
-    /// and the PT100 element.
+    /// and the RTD element, eg with a value found on a bench using `calibrate`.
     ///
     /// # Arguments
     ///
-    /// * `calib` - A 32 bit integer specifying the reference resistance in ohms
-    ///             multiplied by 100, e.g. `40000` for 400 Ohms
+    /// * `calib` - The reference resistance in Ohms, e.g. `400.` or `399.87`
+    pub fn set_calibration(&mut self, calib: f32) {
+        self.calibration = calib;
+    }
+
+    /// Get the calibration reference resistance, in Ohms.
+    pub fn calibration(&self) -> f32 {
+        self.calibration
+    }
+
+    /// Set the resistance offset, in Ohms. This is added to measured resistance,
+    /// correcting errors in the element's nominal resistance (R0).
+    pub fn set_offset(&mut self, offset: f32) {
+        self.offset = offset;
+    }
+
+    /// Get the resistance offset, in Ohms.
+    pub fn offset(&self) -> f32 {
+        self.offset
+    }
+
+    /// Set the lead resistance, in Ohms, for both leads combined. This is subtracted
+    /// from measured resistance. Use with 2-wire RTDs, whose lead resistance isn't
+    /// compensated by the circuit.
+    pub fn set_lead_resistance(&mut self, lead_resistance: f32) {
+        self.lead_resistance = lead_resistance;
+    }
+
+    /// Get the lead resistance, in Ohms.
+    pub fn lead_resistance(&self) -> f32 {
+        self.lead_resistance
+    }
+
+    /// Measure and set the lead resistance, with the probe end of the leads shorted
+    /// in place of the RTD element. Returns the value measured, in Ohms.
+    pub fn measure_lead_resistance<SPI, E, D: DelayMs<u8>>(
+        &mut self,
+        spi: &mut SPI,
+        delay: &mut D,
+    ) -> Result<f32, RtdError<E, CS::Error, RDY::Error>>
+    where
+        SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
+    {
+        let raw = self.read_raw(spi, delay)?;
+        self.check_fault(spi, raw)?;
+
+        // Don't apply the offset: it corrects the element's R0, which is shorted out.
+        let ratio = (raw >> 1) as f32 / 32_768.;
+        self.lead_resistance = ratio * self.calibration;
+        Ok(self.lead_resistance)
+    }
+
+    /// Calibrate by measuring the RTD at a known temperature `T`, in °C; eg in an
+    /// ice bath, or next to a reference thermometer. Set the calibration, and return
+    /// the point measured.
     ///
     /// # Remarks
     ///
-    /// You can perform calibration by putting the sensor in boiling (100 degrees
-    /// Celcius) water and then measuring the raw value using `read_raw`. Calculate
-    /// `calib` as `(13851 << 15) / raw >> 1`.
+    /// With one point, we correct gain, by adjusting the reference resistance. With
+    /// two, we correct both gain and offset. Points should be far apart in temperature.
+    /// `CalSlot::Three` is treated as `CalSlot::Two`. Set lead resistance first, if
+    /// applicable.
     pub fn calibrate<SPI, E, D: DelayMs<u8>>(
         &mut self,
+        slot: CalSlot,
+        T: f32,
         spi: &mut SPI,
         delay: &mut D,
-    ) -> Result<(), E>
+    ) -> Result<CalPtRtd, RtdError<E, CS::Error, RDY::Error>>
     where
         SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
     {
-        // todo
         let raw = self.read_raw(spi, delay)?;
-        self.calibration = ((13851 << 15) / (raw >> 1)) as u32;
+        self.check_fault(spi, raw)?;
+        let pt = CalPtRtd::new(raw, T);
 
-        Ok(())
+        match slot {
+            CalSlot::One => self.cal_1 = Some(pt),
+            CalSlot::Two | CalSlot::Three => self.cal_2 = Some(pt),
+        }
+        self.calibrate_all(self.cal_1, self.cal_2);
+
+        Ok(pt)
+    }
+
+    /// Set calibration from previously-measured points.
+    pub fn calibrate_all(&mut self, pt0: Option<CalPtRtd>, pt1: Option<CalPtRtd>) {
+        self.cal_1 = pt0;
+        self.cal_2 = pt1;
+
+        let R0 = self.type_.r0();
+        // Resistance including leads, and the 15-bit ratio to the reference
+        // resistance, at a point.
+        let fit_pt = |pt: &CalPtRtd| {
+            (
+                resistance_from_temp(pt.T, R0, &self.coeffs) + self.lead_resistance,
+                (pt.raw >> 1) as f32,
+            )
+        };
+
+        match (pt0, pt1) {
+            // Points at the same reading or temperature can't fit a slope; use the latest
+            // as a single point.
+            (Some(c0), Some(c1)) if c0.raw >> 1 == c1.raw >> 1 || c0.T == c1.T => {
+                let (R, ratio) = fit_pt(&c1);
+                self.calibration = R / ratio * 32_768.;
+                self.offset = 0.;
+            }
+            (Some(c0), Some(c1)) => {
+                let (R_0, ratio_0) = fit_pt(&c0);
+                let (R_1, ratio_1) = fit_pt(&c1);
+                // a is the slope, Ohms / ratio.
+                let a = (R_1 - R_0) / (ratio_1 - ratio_0);
+                self.calibration = a * 32_768.;
+                self.offset = R_1 - a * ratio_1;
+            }
+            (Some(c), None) | (None, Some(c)) => {
+                let (R, ratio) = fit_pt(&c);
+                self.calibration = R / ratio * 32_768.;
+                self.offset = 0.;
+            }
+            (None, None) => self.reset_calibration(),
+        }
     }
 
-    /// Determine if a new conversion is available
+    /// Get the calibration points set by `calibrate` or `calibrate_all`.
+    pub fn cal_pts(&self) -> (Option<CalPtRtd>, Option<CalPtRtd>) {
+        (self.cal_1, self.cal_2)
+    }
+
+    /// Restore calibration points, without refitting; used when loading a stored
+    /// calibration.
+    pub(crate) fn set_cal_pts(&mut self, pt0: Option<CalPtRtd>, pt1: Option<CalPtRtd>) {
+        self.cal_1 = pt0;
+        self.cal_2 = pt1;
+    }
+
+    /// Reset calibration to the reference resistance, with no offset.
+    pub fn reset_calibration(&mut self) {
+        self.calibration = self.ref_resistance;
+        self.offset = 0.;
+        self.cal_1 = None;
+        self.cal_2 = None;
+    }
+
+    /// Wait for a conversion to complete.
     ///
     /// # Remarks
     ///
     /// When the module is finished converting the temperature it sets the
     /// ready pin to low. It is automatically returned to high upon reading the
-    /// RTD registers.
-    pub fn _is_ready<I: InputPin<Error = E>, E>(&self, rdy: I) -> Result<bool, E> {
-        rdy.is_low()
-    }
-
-    // todo: Way to pre-set calibration value?
-}
-
-type TempPair = (u16, u16);
-
-// this table contains a pair of temperatures and their
-// corresponding resistance values for a PT100 thermometer
-// The first entry of each pair is the temperature multiplied by 10,
-// while the second entry contains the temperature at that resistance
-// value multiplied by 10, i.e. at 0 deg C, the probe should have a resistance
-// of 100 ohms
-
-// todo: QC this, set it up for pt1000, and make sure it's what you want.
-// todo: Try checking the DS.
-static LOOKUP_TABLE: &[TempPair] = &[
-    (0, 10000),
-    (1000, 10390),
-    (2000, 10779),
-    (3000, 11167),
-    (4000, 11554),
-    (5000, 11940),
-    (6000, 12324),
-    (7000, 12708),
-    (8000, 13090),
-    (9000, 13471),
-    (10000, 13851),
-    (11000, 14229),
-    (12000, 14607),
-    (13000, 14983),
-];
-
-/// Convert the specified PT100 resistance value into a temperature.
-///
-/// # Arguments
-///
-/// * `val` - A 16 bit unsigned integer specifying the resistance in Ohms multiplied by 100, e.g.
-///           13851 would indicate 138.51 Ohms and convert to 100 degrees Celcius.
+    /// RTD registers. Without DRDY, we wait the conversion time of the filter mode.
+    /// With DRDY, returns `RtdError::Timeout` if it doesn't assert in that time.
+    fn wait_conversion<E, D: DelayMs<u8>>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<(), RtdError<E, CS::Error, RDY::Error>> {
+        let conversion_time = self.filter_mode.conversion_time();
+
+        match &self.drdy {
+            Some(rdy) => {
+                for _ in 0..conversion_time {
+                    if rdy.is_low().map_err(RtdError::Drdy)? {
+                        return Ok(());
+                    }
+                    delay.delay_ms(1);
+                }
+
+                if rdy.is_low().map_err(RtdError::Drdy)? {
+                    Ok(())
+                } else {
+                    Err(RtdError::Timeout)
+                }
+            }
+            None => {
+                delay.delay_ms(conversion_time);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// An `Rtd`, borrowed with its SPI bus and delay. Created with `Rtd::source`.
+pub struct RtdSource<'a, CS: OutputPin, RDY: InputPin, SPI, D> {
+    rtd: &'a mut Rtd<CS, RDY>,
+    spi: &'a mut SPI,
+    delay: &'a mut D,
+}
+
+impl<'a, CS, RDY, SPI, D, E, A, BUS> TemperatureSource<A, BUS> for RtdSource<'a, CS, RDY, SPI, D>
+where
+    CS: OutputPin,
+    RDY: InputPin,
+    SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
+    D: DelayMs<u8>,
+{
+    type Error = RtdError<E, CS::Error, RDY::Error>;
+
+    fn read_temp(&mut self, _board: &mut Board<A, BUS>) -> Result<f32, Self::Error> {
+        self.rtd.read(self.spi, self.delay)
+    }
+}
+
+/// Convert temperature in °C to RTD resistance in Ohms, using the Callendar–Van Dusen
+/// equation. `R0` is the resistance at 0°C.
+pub fn resistance_from_temp(T: f32, R0: f32, coeffs: &CvdCoeffs) -> f32 {
+    let CvdCoeffs { A, B, C } = *coeffs;
+
+    if T >= 0. {
+        R0 * (1. + A * T + B * T.powi(2))
+    } else {
+        R0 * (1. + A * T + B * T.powi(2) + C * (T - 100.) * T.powi(3))
+    }
+}
+
+/// Convert RTD resistance in Ohms to temperature in °C, by inverting the
+/// Callendar–Van Dusen equation. `R0` is the resistance at 0°C.
 ///
 /// # Remarks
 ///
-/// The output temperature will be in degrees Celcius multiplied by 100, e.g. 10000 would signify 100.00
-/// degrees Celcius.
-///
-/// *Note*: This won't handle edge cases very well.
-fn lookup_temperature(val: u16, _type: RtdType) -> u32 {
-    // todo: Take into account nominal resistance here, eg 100 or 1000ohm.
-
-    let mut first = &(0, 10000);
-    let mut second = &(1000, 10390);
-    let mut iterator = LOOKUP_TABLE.iter();
-    for a in &mut iterator {
-        first = second;
-        second = &a;
-        if a.1 > val {
+/// At or above 0°C, the equation is a quadratic, which we solve directly. Below 0°C,
+/// we refine that solution with Newton's method, to within 0.001°C.
+pub fn temp_from_resistance(R: f32, R0: f32, coeffs: &CvdCoeffs) -> f32 {
+    let CvdCoeffs { A, B, C } = *coeffs;
+    let Z = R / R0;
+
+    let mut T = if B == 0. {
+        (Z - 1.) / A
+    } else {
+        (-A + (A.powi(2) - 4. * B * (1. - Z)).sqrt()) / (2. * B)
+    };
+
+    if R >= R0 {
+        return T;
+    }
+
+    for _ in 0..CVD_MAX_ITERS {
+        let err = resistance_from_temp(T, R0, coeffs) - R;
+        let slope = R0 * (A + 2. * B * T + C * (4. * T.powi(3) - 300. * T.powi(2)));
+
+        let step = err / slope;
+        T -= step;
+
+        if step.abs() < CVD_TOLERANCE {
             break;
         }
     }
-    let second = iterator.next();
 
-    if let Some(second) = second {
-        // Don't crash the program with a divide-by-0 error
-        // if no device is connected.
-        if (second.1 - first.1) == 0 {
-            return 0;
+    T
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Spi;
+
+    impl Write<u8> for Spi {
+        type Error = Infallible;
+
+        fn write(&mut self, _words: &[u8]) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    impl Transfer<u8> for Spi {
+        type Error = Infallible;
+
+        fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Infallible> {
+            Ok(words)
+        }
+    }
+
+    struct Cs;
+
+    impl OutputPin for Cs {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    /// A DRDY pin that never asserts.
+    struct Stuck;
+
+    impl InputPin for Stuck {
+        type Error = Infallible;
+
+        fn is_high(&self) -> Result<bool, Infallible> {
+            Ok(true)
+        }
+
+        fn is_low(&self) -> Result<bool, Infallible> {
+            Ok(false)
+        }
+    }
+
+    struct Delay;
+
+    impl DelayMs<u8> for Delay {
+        fn delay_ms(&mut self, _ms: u8) {}
+    }
+
+    #[test]
+    fn config_round_trip() {
+        for &wires in &[Wires::Two, Wires::Three, Wires::Four] {
+            for &fault_cycle in &[None, Some(FaultCycle::Automatic), Some(FaultCycle::Manual)] {
+                let config = Config {
+                    vbias: Vbias::On,
+                    conversion_mode: ConversionMode::Auto,
+                    one_shot: OneShot::Cleared,
+                    wires,
+                    fault_cycle,
+                    fault_clear: false,
+                    filter_mode: FilterMode::Filter50Hz,
+                };
+
+                assert_eq!(Config::from_byte(config.to_byte(), wires), config);
+            }
+        }
+
+        // The 3-wire bit, read by a driver configured for 3 wires.
+        assert_eq!(
+            Config::from_byte(0b0001_0000, Wires::Three).wires,
+            Wires::Three
+        );
+        assert_eq!(Config::from_byte(0, Wires::Three).wires, Wires::Two);
+    }
+
+    #[test]
+    fn config_wires_mismatch() {
+        let mut rtd = Rtd::new(&mut Spi, Cs, RtdType::Pt100, Wires::Three).unwrap();
+
+        // `Spi` reads back 0, so the chip reports 2 or 4 wires, as after a reset.
+        assert!(matches!(
+            rtd.read_config(&mut Spi),
+            Err(RtdError::WiresMismatch(Config {
+                wires: Wires::Two,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn two_point_calibration() {
+        let mut rtd = Rtd::new(&mut Spi, Cs, RtdType::Pt100, Wires::Three).unwrap();
+        let (pt0, pt1) = (CalPtRtd::new(16_000, 0.), CalPtRtd::new(22_200, 100.));
+        rtd.calibrate_all(Some(pt0), Some(pt1));
+
+        // Ratios of 8,000 and 11,100 map to 100Ω and 138.51Ω.
+        let a = (138.5055 - 100.) / 3_100.;
+        assert!((rtd.calibration() - a * 32_768.).abs() < 0.01);
+        assert!((rtd.offset() - (100. - a * 8_000.)).abs() < 0.01);
+
+        for pt in &[pt0, pt1] {
+            let R = rtd.resistance_from_raw(pt.raw);
+            assert!((temp_from_resistance(R, 100., &CvdCoeffs::IEC_60751) - pt.T).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn two_point_calibration_at_same_temp() {
+        let mut rtd = Rtd::new(&mut Spi, Cs, RtdType::Pt100, Wires::Three).unwrap();
+        let pt1 = CalPtRtd::new(16_100, 0.);
+        rtd.calibrate_all(Some(CalPtRtd::new(16_000, 0.)), Some(pt1));
+
+        // The latest point, as a single-point calibration.
+        assert_eq!(rtd.offset(), 0.);
+        assert!((rtd.resistance_from_raw(pt1.raw) - 100.).abs() < 0.01);
+        assert!((rtd.resistance_from_raw(24_000) - 149.07).abs() < 0.01);
+    }
+
+    #[test]
+    fn drdy_timeout() {
+        let config = RtdConfig::new(RtdType::Pt100, Wires::Three);
+        let mut rtd = Rtd::new_with_drdy(&mut Spi, Cs, Stuck, config).unwrap();
+
+        assert!(matches!(
+            rtd.read_raw(&mut Spi, &mut Delay),
+            Err(RtdError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn cvd_matches_iec_60751() {
+        // Pt100 reference table values, in Ohms.
+        for &(T, R) in &[(-200., 18.52), (0., 100.), (100., 138.51), (850., 390.48)] {
+            let R_calc = resistance_from_temp(T, 100., &CvdCoeffs::IEC_60751);
+            assert!((R_calc - R).abs() < 0.01, "{}°C: {} Ω", T, R_calc);
+        }
+    }
+
+    #[test]
+    fn cvd_round_trip() {
+        for &T in &[-200., -100., -0.5, 0., 25., 100., 850.] {
+            let R = resistance_from_temp(T, 100., &CvdCoeffs::IEC_60751);
+            let T_calc = temp_from_resistance(R, 100., &CvdCoeffs::IEC_60751);
+            assert!((T_calc - T).abs() < 0.01, "{}°C: {}°C", T, T_calc);
         }
-        (second.0 - first.0) as u32 * (val - first.1) as u32 / (second.1 - first.1) as u32
-            + first.0 as u32
-    } else {
-        0
     }
 }
@@ -0,0 +1,318 @@
+//! Run conversions on several ADCs at once. Each ADS1115 conversion blocks for its
+//! full duration, so reading pH on 0x48, and ORP on 0x49 in turn takes 4 conversions
+//! back to back. Here, we start a conversion on every idle ADC, and collect results
+//! as each completes, so conversions on different ADCs overlap.
+
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+
+use crate::{
+    temp_from_voltage_cal, AdcChannel, AdcError, AdcInput, FullScale, I2cAdc, OrpSensor, PhSensor,
+    MAX_SAMPLES,
+};
+
+#[derive(Clone, Copy, Debug)]
+/// A conversion to run with `read_parallel`.
+pub struct Conversion {
+    pub adc: I2cAdc,
+    pub input: AdcInput,
+    pub range: FullScale,
+}
+
+impl Conversion {
+    pub fn new(adc: I2cAdc, input: AdcInput, range: FullScale) -> Self {
+        Self { adc, input, range }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// pH, ORP, and the temperature of each board, measured together.
+pub struct Snapshot {
+    pub pH: f32,
+    /// In mV.
+    pub ORP: f32,
+    /// The pH board's onboard temperature, in °C. Used to compensate pH.
+    pub T_ph: f32,
+    /// The ORP board's onboard temperature, in °C.
+    pub T_orp: f32,
+}
+
+/// Run `conversions`, returning their results, in Volts, in the same order.
+/// Conversions on different ADCs run at once; those on the same ADC run in the order
+/// listed. Conversions listed together on different ADCs start together, so list
+/// ones to time-align at the same position for each ADC.
+pub fn read_parallel<I2C, E, const N: usize>(
+    conversions: &[Conversion; N],
+    i2c: &mut I2C,
+) -> Result<[f32; N], AdcError<E>>
+where
+    I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+{
+    let mut result = [0.; N];
+    let mut started = [false; N];
+    let mut done = [false; N];
+
+    loop {
+        // Start the next conversion on each idle ADC.
+        for i in 0..N {
+            let busy = (0..N).any(|j| {
+                ((started[j] && !done[j]) || (!started[j] && j < i))
+                    && conversions[j].adc.addr == conversions[i].adc.addr
+            });
+
+            if !started[i] && !busy {
+                let c = &conversions[i];
+                c.adc.start(c.input, c.range, i2c)?;
+                started[i] = true;
+            }
+        }
+
+        if done.iter().all(|d| *d) {
+            return Ok(result);
+        }
+
+        for i in 0..N {
+            if started[i] && !done[i] {
+                let c = &conversions[i];
+                if let Some(V) = c.adc.poll(c.range, i2c)? {
+                    result[i] = V;
+                    done[i] = true;
+                }
+            }
+        }
+    }
+}
+
+/// Read pH and ORP, with the ADCs' conversions overlapping: the probes are measured
+/// at the same time, then both boards' onboard temperature sensors. The sensors'
+/// oversampling, ADC calibration and Kalman filters are applied as with `read`. pH is
+/// compensated using the pH board's onboard temperature sensor. The sensors must be on
+/// different ADCs; eg `PhSensor::new` and `OrpSensor::new_alt_addr`.
+pub fn read_snapshot<I2C, E>(
+    ph: &mut PhSensor,
+    orp: &mut OrpSensor,
+    i2c: &mut I2C,
+) -> Result<Snapshot, AdcError<E>>
+where
+    I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+{
+    let probes = [
+        Conversion::new(ph.front_end.adc, AdcInput::Probe, FullScale::V2_048),
+        Conversion::new(orp.front_end.adc, AdcInput::Probe, FullScale::V2_048),
+    ];
+    let temps = [
+        Conversion::new(
+            ph.front_end.adc,
+            AdcInput::Single(AdcChannel::A2),
+            FullScale::V2_048,
+        ),
+        Conversion::new(
+            orp.front_end.adc,
+            AdcInput::Single(AdcChannel::A2),
+            FullScale::V2_048,
+        ),
+    ];
+
+    // Take the same number of samples from each probe, so they stay aligned; each
+    // sensor combines as many as its oversampling calls for.
+    let n_ph = ph.front_end.oversampling.count();
+    let n_orp = orp.front_end.oversampling.count();
+
+    let mut samples_ph = [0.; MAX_SAMPLES];
+    let mut samples_orp = [0.; MAX_SAMPLES];
+    for i in 0..n_ph.max(n_orp) {
+        let [V_ph, V_orp] = read_parallel(&probes, i2c)?;
+        samples_ph[i] = V_ph;
+        samples_orp[i] = V_orp;
+    }
+    let [V_T_ph, V_T_orp] = read_parallel(&temps, i2c)?;
+
+    let V_ph = ph.front_end.adc_cal.apply(
+        ph.front_end
+            .oversampling
+            .combine(&mut samples_ph[..n_ph])
+            .value,
+    );
+    let V_orp = orp.front_end.adc_cal.apply(
+        orp.front_end
+            .oversampling
+            .combine(&mut samples_orp[..n_orp])
+            .value,
+    );
+
+    let T_ph = temp_from_voltage_cal(
+        ph.front_end.adc_cal.apply(V_T_ph),
+        ph.front_end.temp_sensor,
+        &ph.front_end.temp_cal_1,
+        &ph.front_end.temp_cal_2,
+    );
+    let T_orp = temp_from_voltage_cal(
+        orp.front_end.adc_cal.apply(V_T_orp),
+        orp.front_end.temp_sensor,
+        &orp.front_end.temp_cal_1,
+        &orp.front_end.temp_cal_2,
+    );
+
+    ph.predict();
+    let pH = ph.ph_from_voltage(V_ph, T_ph);
+    ph.update_filter(pH);
+
+    orp.predict();
+    let ORP = orp.orp_from_voltage(V_orp);
+    orp.update_filter(ORP);
+
+    Ok(Snapshot {
+        // `filter.x` is mean, variance. We only care about the mean
+        pH: ph.filter.x[0],
+        ORP: orp.filter.x[0],
+        T_ph,
+        T_orp,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+
+    use super::*;
+    use crate::{
+        adc::{CFG_REG, CONV_REG},
+        AdcModel,
+    };
+
+    /// An ADS1115 whose conversions complete after `POLLS` polls.
+    #[derive(Default)]
+    struct Chip {
+        addr: u8,
+        busy: bool,
+        polls_left: u8,
+        starts: i16,
+    }
+
+    const POLLS: u8 = 2;
+
+    /// An I2C bus with two ADS1115s. Each conversion reads `addr * 100 + n`, for the
+    /// chip's `n`th conversion.
+    struct Bus {
+        chips: [Chip; 2],
+        /// The most conversions in progress at once.
+        max_busy: usize,
+    }
+
+    impl Bus {
+        fn new() -> Self {
+            Self {
+                chips: [
+                    Chip {
+                        addr: 0x48,
+                        ..Default::default()
+                    },
+                    Chip {
+                        addr: 0x49,
+                        ..Default::default()
+                    },
+                ],
+                max_busy: 0,
+            }
+        }
+
+        fn chip(&mut self, addr: u8) -> &mut Chip {
+            self.chips.iter_mut().find(|c| c.addr == addr).unwrap()
+        }
+    }
+
+    impl Write for Bus {
+        type Error = Infallible;
+
+        fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Infallible> {
+            assert_eq!(bytes[0], CFG_REG);
+            let chip = self.chip(addr);
+            assert!(!chip.busy, "conversion started on a busy ADC");
+            chip.busy = true;
+            chip.polls_left = POLLS;
+            chip.starts += 1;
+
+            let busy = self.chips.iter().filter(|c| c.busy).count();
+            self.max_busy = self.max_busy.max(busy);
+            Ok(())
+        }
+    }
+
+    impl WriteRead for Bus {
+        type Error = Infallible;
+
+        fn write_read(&mut self, addr: u8, bytes: &[u8], buf: &mut [u8]) -> Result<(), Infallible> {
+            let chip = self.chip(addr);
+            match bytes[0] {
+                CFG_REG => {
+                    if chip.polls_left > 0 {
+                        chip.polls_left -= 1;
+                        buf.copy_from_slice(&[0, 0]);
+                    } else {
+                        chip.busy = false;
+                        buf.copy_from_slice(&[0x80, 0]);
+                    }
+                }
+                CONV_REG => {
+                    assert!(!chip.busy, "result read before the conversion completed");
+                    let code = addr as i16 * 100 + chip.starts;
+                    buf.copy_from_slice(&code.to_be_bytes());
+                }
+                _ => panic!("unexpected register"),
+            }
+            Ok(())
+        }
+    }
+
+    impl Read for Bus {
+        type Error = Infallible;
+
+        fn read(&mut self, _: u8, _: &mut [u8]) -> Result<(), Infallible> {
+            panic!("ADS1115s aren't read without a register")
+        }
+    }
+
+    fn code(V: f32) -> i16 {
+        (V / FullScale::V2_048.volts() * 32_768.).round() as i16
+    }
+
+    #[test]
+    fn parallel_conversions() {
+        let adc_1 = I2cAdc::new(AdcModel::Ads1115, 0x48);
+        let adc_2 = I2cAdc::new(AdcModel::Ads1115, 0x49);
+        let a2 = AdcInput::Single(AdcChannel::A2);
+
+        let conversions = [
+            Conversion::new(adc_1, AdcInput::Probe, FullScale::V2_048),
+            Conversion::new(adc_1, a2, FullScale::V2_048),
+            Conversion::new(adc_2, AdcInput::Probe, FullScale::V2_048),
+            Conversion::new(adc_2, a2, FullScale::V2_048),
+        ];
+
+        let mut bus = Bus::new();
+        let result = read_parallel(&conversions, &mut bus).unwrap();
+
+        // The `Bus` panics if a conversion starts on a busy ADC, so conversions on the
+        // same ADC ran in turn, in the order listed.
+        assert_eq!(code(result[0]), 0x48 * 100 + 1);
+        assert_eq!(code(result[1]), 0x48 * 100 + 2);
+        assert_eq!(code(result[2]), 0x49 * 100 + 1);
+        assert_eq!(code(result[3]), 0x49 * 100 + 2);
+        // Conversions on different ADCs overlapped.
+        assert_eq!(bus.max_busy, 2);
+    }
+
+    #[test]
+    fn snapshot_overlaps_conversions() {
+        let mut ph = PhSensor::new(1.);
+        let mut orp = OrpSensor::new_alt_addr(1.);
+
+        let mut bus = Bus::new();
+        read_snapshot(&mut ph, &mut orp, &mut bus).unwrap();
+
+        // One probe reading per sample, then the temperature, on each ADC.
+        let n = ph.front_end.oversampling.count() as i16 + 1;
+        assert_eq!(bus.chips[0].starts, n);
+        assert_eq!(bus.chips[1].starts, n);
+        assert_eq!(bus.max_busy, 2);
+    }
+}
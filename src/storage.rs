@@ -1,67 +1,503 @@
-////! This file contains code used to [de]serialize calibration
-////! points (etc) into byte arrays, for use with storing on
-////! flash chips etc.
-//
-//use crate::{CalPt, OrpCalPt, EcCalPt};
-//
-//impl CalPt {
-//    pub fn to_bytes(&self) -> [u8; 37] {
-//        let mut r = [0; 37];
-//
-//        let mut addr = 0;
-//
-//        // todo: DRY
-//        for byte in &float_to_bytes(self.ph_cal.0.V) {
-//            r[addr] = *byte;
-//            addr += 1;
-//        }
-//        for byte in &float_to_bytes(self.ph_cal.0.pH) {
-//            r[addr] = *byte;
-//            addr += 1;
-//        }
-//        for byte in &float_to_bytes(self.ph_cal.0.T) {
-//            r[addr] = *byte;
-//            addr += 1;
-//        }
-//
-//        for byte in &float_to_bytes(self.ph_cal.1.V) {
-//            r[addr] = *byte;
-//            addr += 1;
-//        }
-//        for byte in &float_to_bytes(self.ph_cal.1.pH) {
-//            r[addr] = *byte;
-//            addr += 1;
-//        }
-//        for byte in &float_to_bytes(self.ph_cal.1.T) {
-//            r[addr] = *byte;
-//            addr += 1;
-//        }
-//
-//        match self.ph_cal.2 {
-//            Some(c) => {
-//                r[addr] = 1;
-//                addr += 1;
-//
-//                for byte in &float_to_bytes(c.V) {
-//                    r[addr] = *byte;
-//                    addr += 1;
-//                }
-//                for byte in &float_to_bytes(c.pH) {
-//                    r[addr] = *byte;
-//                    addr += 1;
-//                }
-//                for byte in &float_to_bytes(c.T) {
-//                    r[addr] = *byte;
-//                    addr += 1;
-//                }
-//            }
-//            None => {
-//                r[addr] = 0;
-//                addr += 13;
-//            }
-//        }
-//
-//        r
-//    }
-//
-//}
+//! This file contains code used to [de]serialize calibration
+//! points (etc) into byte arrays, for use with storing on
+//! flash chips etc. Floats are stored big-endian. Optional points
+//! are preceded by a byte that's 1 if present, and 0 if not.
+
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+
+use crate::{
+    AdcCal, CalPt, CalPtIse, CalPtOrp, CalPtRtd, CalPtT, FrontEnd, IseSensor, OrpReference,
+    OrpSensor, PhSensor, Rtd, TempSensorModel,
+};
+
+/// Size of a serialized pH sensor calibration, in bytes.
+pub const PH_CAL_SIZE: usize = 72;
+/// Size of a serialized ORP sensor calibration, in bytes.
+pub const ORP_CAL_SIZE: usize = 53;
+/// Size of a serialized ISE sensor calibration, in bytes.
+pub const ISE_CAL_SIZE: usize = 77;
+/// Size of a serialized RTD calibration, in bytes.
+pub const RTD_CAL_SIZE: usize = 26;
+
+impl<A> PhSensor<A> {
+    /// Serialize calibration, including the onboard temperature sensor's model and
+    /// calibration, and the ADC's calibration.
+    pub fn cal_to_bytes(&self) -> [u8; PH_CAL_SIZE] {
+        let mut r = [0; PH_CAL_SIZE];
+        let mut addr = 0;
+
+        write_cal_pt(&mut r, &mut addr, &self.cal_1);
+        write_cal_pt(&mut r, &mut addr, &self.cal_2);
+
+        match self.cal_3 {
+            Some(c) => {
+                r[addr] = 1;
+                addr += 1;
+                write_cal_pt(&mut r, &mut addr, &c);
+            }
+            None => {
+                r[addr] = 0;
+                addr += 13;
+            }
+        }
+
+        write_temp_cal(&mut r, &mut addr, &self.front_end);
+        write_adc_cal(&mut r, &mut addr, &self.front_end.adc_cal);
+
+        r
+    }
+
+    /// Load calibration serialized with `cal_to_bytes`.
+    pub fn cal_from_bytes(&mut self, buf: &[u8; PH_CAL_SIZE]) {
+        let mut addr = 0;
+
+        self.cal_1 = read_cal_pt(buf, &mut addr);
+        self.cal_2 = read_cal_pt(buf, &mut addr);
+
+        self.cal_3 = if buf[addr] == 1 {
+            addr += 1;
+            Some(read_cal_pt(buf, &mut addr))
+        } else {
+            addr += 13;
+            None
+        };
+
+        read_temp_cal(buf, &mut addr, &mut self.front_end);
+
+        self.front_end.adc_cal = read_adc_cal(buf, &mut addr);
+    }
+}
+
+impl<A> OrpSensor<A> {
+    /// Serialize calibration, including the reference electrode, the onboard
+    /// temperature sensor's model, and its and the ADC's calibration.
+    pub fn cal_to_bytes(&self) -> [u8; ORP_CAL_SIZE] {
+        let mut r = [0; ORP_CAL_SIZE];
+        let mut addr = 0;
+
+        write_cal_pt_orp(&mut r, &mut addr, &self.cal_1);
+
+        match self.cal_2 {
+            Some(c) => {
+                r[addr] = 1;
+                addr += 1;
+                write_cal_pt_orp(&mut r, &mut addr, &c);
+            }
+            None => {
+                r[addr] = 0;
+                addr += 9;
+            }
+        }
+
+        r[addr] = match self.reference {
+            OrpReference::AgAgCl3M => 0,
+            OrpReference::SaturatedCalomel => 1,
+            OrpReference::She => 2,
+        };
+        addr += 1;
+
+        write_temp_cal(&mut r, &mut addr, &self.front_end);
+        write_adc_cal(&mut r, &mut addr, &self.front_end.adc_cal);
+
+        r
+    }
+
+    /// Load calibration serialized with `cal_to_bytes`.
+    pub fn cal_from_bytes(&mut self, buf: &[u8; ORP_CAL_SIZE]) {
+        let mut addr = 0;
+
+        self.cal_1 = read_cal_pt_orp(buf, &mut addr);
+
+        self.cal_2 = if buf[addr] == 1 {
+            addr += 1;
+            Some(read_cal_pt_orp(buf, &mut addr))
+        } else {
+            addr += 9;
+            None
+        };
+
+        self.reference = match buf[addr] {
+            1 => OrpReference::SaturatedCalomel,
+            2 => OrpReference::She,
+            _ => OrpReference::AgAgCl3M,
+        };
+        addr += 1;
+
+        read_temp_cal(buf, &mut addr, &mut self.front_end);
+
+        self.front_end.adc_cal = read_adc_cal(buf, &mut addr);
+    }
+}
+
+impl<A> IseSensor<A> {
+    /// Serialize calibration, including the ISA offset, the onboard temperature
+    /// sensor's model, and its and the ADC's calibration. The ion and unit aren't
+    /// included; points are stored in mol/L.
+    pub fn cal_to_bytes(&self) -> [u8; ISE_CAL_SIZE] {
+        let mut r = [0; ISE_CAL_SIZE];
+        let mut addr = 0;
+
+        write_cal_pt_ise(&mut r, &mut addr, &self.cal_1);
+
+        for cal in &[self.cal_2, self.cal_3] {
+            match cal {
+                Some(c) => {
+                    r[addr] = 1;
+                    addr += 1;
+                    write_cal_pt_ise(&mut r, &mut addr, c);
+                }
+                None => {
+                    r[addr] = 0;
+                    addr += 13;
+                }
+            }
+        }
+
+        write_f32(&mut r, &mut addr, self.isa_offset);
+
+        write_temp_cal(&mut r, &mut addr, &self.front_end);
+        write_adc_cal(&mut r, &mut addr, &self.front_end.adc_cal);
+
+        r
+    }
+
+    /// Load calibration serialized with `cal_to_bytes`.
+    pub fn cal_from_bytes(&mut self, buf: &[u8; ISE_CAL_SIZE]) {
+        let mut addr = 0;
+
+        self.cal_1 = read_cal_pt_ise(buf, &mut addr);
+
+        let mut pts = [None, None];
+        for pt in pts.iter_mut() {
+            if buf[addr] == 1 {
+                addr += 1;
+                *pt = Some(read_cal_pt_ise(buf, &mut addr));
+            } else {
+                addr += 13;
+            }
+        }
+        self.cal_2 = pts[0];
+        self.cal_3 = pts[1];
+
+        self.isa_offset = read_f32(buf, &mut addr);
+
+        read_temp_cal(buf, &mut addr, &mut self.front_end);
+
+        self.front_end.adc_cal = read_adc_cal(buf, &mut addr);
+    }
+}
+
+impl<CS: OutputPin, RDY: InputPin> Rtd<CS, RDY> {
+    /// Serialize calibration, including lead resistance and calibration points, eg to
+    /// apply a calibration done on a bench in the field. Since the points are kept,
+    /// recalibrating one of them later refits from both.
+    pub fn cal_to_bytes(&self) -> [u8; RTD_CAL_SIZE] {
+        let mut r = [0; RTD_CAL_SIZE];
+        let mut addr = 0;
+
+        write_f32(&mut r, &mut addr, self.calibration());
+        write_f32(&mut r, &mut addr, self.offset());
+        write_f32(&mut r, &mut addr, self.lead_resistance());
+
+        let (pt0, pt1) = self.cal_pts();
+        write_cal_pt_rtd(&mut r, &mut addr, &pt0);
+        write_cal_pt_rtd(&mut r, &mut addr, &pt1);
+
+        r
+    }
+
+    /// Load calibration serialized with `cal_to_bytes`.
+    pub fn cal_from_bytes(&mut self, buf: &[u8; RTD_CAL_SIZE]) {
+        let mut addr = 0;
+
+        self.set_calibration(read_f32(buf, &mut addr));
+        self.set_offset(read_f32(buf, &mut addr));
+        self.set_lead_resistance(read_f32(buf, &mut addr));
+
+        let pt0 = read_cal_pt_rtd(buf, &mut addr);
+        let pt1 = read_cal_pt_rtd(buf, &mut addr);
+        self.set_cal_pts(pt0, pt1);
+    }
+}
+
+fn write_f32(buf: &mut [u8], addr: &mut usize, val: f32) {
+    buf[*addr..*addr + 4].copy_from_slice(&val.to_be_bytes());
+    *addr += 4;
+}
+
+fn read_f32(buf: &[u8], addr: &mut usize) -> f32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&buf[*addr..*addr + 4]);
+    *addr += 4;
+    f32::from_be_bytes(bytes)
+}
+
+fn write_cal_pt(buf: &mut [u8], addr: &mut usize, pt: &CalPt) {
+    write_f32(buf, addr, pt.V);
+    write_f32(buf, addr, pt.pH);
+    write_f32(buf, addr, pt.T);
+}
+
+fn read_cal_pt(buf: &[u8], addr: &mut usize) -> CalPt {
+    let V = read_f32(buf, addr);
+    let pH = read_f32(buf, addr);
+    let T = read_f32(buf, addr);
+    CalPt::new(V, pH, T)
+}
+
+fn write_cal_pt_orp(buf: &mut [u8], addr: &mut usize, pt: &CalPtOrp) {
+    write_f32(buf, addr, pt.V);
+    write_f32(buf, addr, pt.ORP);
+}
+
+fn read_cal_pt_orp(buf: &[u8], addr: &mut usize) -> CalPtOrp {
+    let V = read_f32(buf, addr);
+    let ORP = read_f32(buf, addr);
+    CalPtOrp::new(V, ORP)
+}
+
+fn write_cal_pt_ise(buf: &mut [u8], addr: &mut usize, pt: &CalPtIse) {
+    write_f32(buf, addr, pt.V);
+    write_f32(buf, addr, pt.C);
+    write_f32(buf, addr, pt.T);
+}
+
+fn read_cal_pt_ise(buf: &[u8], addr: &mut usize) -> CalPtIse {
+    let V = read_f32(buf, addr);
+    let C = read_f32(buf, addr);
+    let T = read_f32(buf, addr);
+    CalPtIse::new(V, C, T)
+}
+
+/// Write an optional RTD calibration point. Takes 7 bytes.
+fn write_cal_pt_rtd(buf: &mut [u8], addr: &mut usize, pt: &Option<CalPtRtd>) {
+    match pt {
+        Some(p) => {
+            buf[*addr] = 1;
+            buf[*addr + 1..*addr + 3].copy_from_slice(&p.raw.to_be_bytes());
+            *addr += 3;
+            write_f32(buf, addr, p.T);
+        }
+        None => {
+            buf[*addr] = 0;
+            *addr += 7;
+        }
+    }
+}
+
+fn read_cal_pt_rtd(buf: &[u8], addr: &mut usize) -> Option<CalPtRtd> {
+    if buf[*addr] == 1 {
+        let raw = u16::from_be_bytes([buf[*addr + 1], buf[*addr + 2]]);
+        *addr += 3;
+        let T = read_f32(buf, addr);
+        Some(CalPtRtd::new(raw, T))
+    } else {
+        *addr += 7;
+        None
+    }
+}
+
+/// Write the onboard temperature sensor's model, and its calibration. Takes 27 bytes.
+fn write_temp_cal<A>(buf: &mut [u8], addr: &mut usize, front_end: &FrontEnd<A>) {
+    buf[*addr] = match front_end.temp_sensor {
+        TempSensorModel::Lm61 => 0,
+        TempSensorModel::Tmp36 => 1,
+        TempSensorModel::Lm35 => 2,
+        TempSensorModel::Mcp9700 => 3,
+        TempSensorModel::Mcp9701 => 4,
+        TempSensorModel::Custom { .. } => 5,
+    };
+    *addr += 1;
+
+    // Offset and slope are only used by `Custom`, but we write them for any model, to
+    // keep the layout fixed.
+    write_f32(buf, addr, front_end.temp_sensor.offset());
+    write_f32(buf, addr, front_end.temp_sensor.slope());
+
+    for cal in &[front_end.temp_cal_1, front_end.temp_cal_2] {
+        match cal {
+            Some(c) => {
+                buf[*addr] = 1;
+                *addr += 1;
+                write_f32(buf, addr, c.V);
+                write_f32(buf, addr, c.T);
+            }
+            None => {
+                buf[*addr] = 0;
+                *addr += 9;
+            }
+        }
+    }
+}
+
+/// Read the onboard temperature sensor's model and calibration written with
+/// `write_temp_cal`.
+fn read_temp_cal<A>(buf: &[u8], addr: &mut usize, front_end: &mut FrontEnd<A>) {
+    let tag = buf[*addr];
+    *addr += 1;
+    let offset = read_f32(buf, addr);
+    let slope = read_f32(buf, addr);
+
+    front_end.temp_sensor = match tag {
+        1 => TempSensorModel::Tmp36,
+        2 => TempSensorModel::Lm35,
+        3 => TempSensorModel::Mcp9700,
+        4 => TempSensorModel::Mcp9701,
+        5 => TempSensorModel::Custom { offset, slope },
+        _ => TempSensorModel::Lm61,
+    };
+
+    let mut result = [None, None];
+
+    for cal in result.iter_mut() {
+        if buf[*addr] == 1 {
+            *addr += 1;
+            let V = read_f32(buf, addr);
+            let T = read_f32(buf, addr);
+            *cal = Some(CalPtT::new(V, T));
+        } else {
+            *addr += 9;
+        }
+    }
+
+    front_end.temp_cal_1 = result[0];
+    front_end.temp_cal_2 = result[1];
+}
+
+/// Write ADC offset and gain calibration. Takes 8 bytes.
+fn write_adc_cal(buf: &mut [u8], addr: &mut usize, cal: &AdcCal) {
+    write_f32(buf, addr, cal.offset);
+    write_f32(buf, addr, cal.gain);
+}
+
+fn read_adc_cal(buf: &[u8], addr: &mut usize) -> AdcCal {
+    let offset = read_f32(buf, addr);
+    let gain = read_f32(buf, addr);
+    AdcCal::new(offset, gain)
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+
+    use embedded_hal::blocking::spi::{Transfer, Write};
+
+    use super::*;
+    use crate::{ConcUnit, Ion, RtdType, Wires};
+
+    struct Spi;
+
+    impl Write<u8> for Spi {
+        type Error = Infallible;
+
+        fn write(&mut self, _words: &[u8]) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    impl Transfer<u8> for Spi {
+        type Error = Infallible;
+
+        fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Infallible> {
+            Ok(words)
+        }
+    }
+
+    struct Cs;
+
+    impl OutputPin for Cs {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn ph_round_trip() {
+        let mut sensor = PhSensor::new(1.);
+        sensor.calibrate_all(
+            CalPt::new(-0.18, 10., 24.),
+            CalPt::new(0., 7., 25.),
+            Some(CalPt::new(0.17, 4., 26.)),
+        );
+        sensor.front_end.temp_cal_1 = Some(CalPtT::new(0.85, 24.));
+        sensor.front_end.temp_sensor = TempSensorModel::Custom {
+            offset: 0.3,
+            slope: 0.02,
+        };
+        sensor.front_end.adc_cal = AdcCal::new(0.001, 1.01);
+
+        let mut loaded = PhSensor::new(1.);
+        loaded.cal_from_bytes(&sensor.cal_to_bytes());
+
+        assert_eq!(loaded.cal_1.V, -0.18);
+        assert_eq!(loaded.cal_2.pH, 7.);
+        assert_eq!(loaded.cal_3.unwrap().T, 26.);
+        assert_eq!(loaded.front_end.temp_cal_1.unwrap().V, 0.85);
+        assert!(loaded.front_end.temp_cal_2.is_none());
+        assert_eq!(loaded.front_end.temp_sensor.offset(), 0.3);
+        assert_eq!(loaded.front_end.temp_sensor.slope(), 0.02);
+        assert_eq!(loaded.front_end.adc_cal.offset, 0.001);
+        assert_eq!(loaded.front_end.adc_cal.gain, 1.01);
+    }
+
+    #[test]
+    fn orp_round_trip() {
+        let mut sensor = OrpSensor::new(1.);
+        sensor.calibrate_all(CalPtOrp::new(0.2, 220.), Some(CalPtOrp::new(0.45, 470.)));
+        sensor.reference = OrpReference::SaturatedCalomel;
+        sensor.front_end.temp_sensor = TempSensorModel::Tmp36;
+
+        let mut loaded = OrpSensor::new(1.);
+        loaded.cal_from_bytes(&sensor.cal_to_bytes());
+
+        assert_eq!(loaded.cal_1.ORP, 220.);
+        assert_eq!(loaded.cal_2.unwrap().V, 0.45);
+        assert_eq!(loaded.reference, OrpReference::SaturatedCalomel);
+        assert!(matches!(
+            loaded.front_end.temp_sensor,
+            TempSensorModel::Tmp36
+        ));
+        assert!(loaded.front_end.temp_cal_1.is_none());
+    }
+
+    #[test]
+    fn ise_round_trip() {
+        let mut sensor = IseSensor::new(Ion::Nitrate, ConcUnit::MgPerL, 1.);
+        sensor.cal_2 = Some(CalPtIse::new(0.06, 0.001, 24.));
+        sensor.isa_offset = 0.003;
+
+        let mut loaded = IseSensor::new(Ion::Nitrate, ConcUnit::MgPerL, 1.);
+        loaded.cal_from_bytes(&sensor.cal_to_bytes());
+
+        assert_eq!(loaded.cal_1.C, sensor.cal_1.C);
+        assert_eq!(loaded.cal_2.unwrap().C, 0.001);
+        assert!(loaded.cal_3.is_none());
+        assert_eq!(loaded.isa_offset, 0.003);
+    }
+
+    #[test]
+    fn rtd_round_trip() {
+        let mut rtd = Rtd::new(&mut Spi, Cs, RtdType::Pt100, Wires::Three).unwrap();
+        rtd.set_calibration(1.002);
+        rtd.set_offset(-0.05);
+        rtd.set_lead_resistance(0.4);
+        rtd.set_cal_pts(Some(CalPtRtd::new(8_000, 0.)), None);
+
+        let mut loaded = Rtd::new(&mut Spi, Cs, RtdType::Pt100, Wires::Three).unwrap();
+        loaded.cal_from_bytes(&rtd.cal_to_bytes());
+
+        assert_eq!(loaded.calibration(), 1.002);
+        assert_eq!(loaded.offset(), -0.05);
+        assert_eq!(loaded.lead_resistance(), 0.4);
+
+        let (pt0, pt1) = loaded.cal_pts();
+        assert_eq!(pt0.unwrap().raw, 8_000);
+        assert!(pt1.is_none());
+    }
+}
@@ -0,0 +1,188 @@
+//! Temperature sources used for compensation, eg of pH readings. Any type implementing
+//! `TemperatureSource` can be passed to `PhSensor::read` and similar methods: the
+//! onboard temperature sensor or a fixed value via `TempSource`, an `Rtd` via
+//! `Rtd::source`, or a user type.
+
+use crate::{
+    temp_from_voltage, temp_from_voltage_cal, AdcCal, AdcChannel, AdcInput, CalPtT, Error,
+    FullScale, TempSource, VoltageSource,
+};
+
+#[derive(Clone, Copy, Debug)]
+/// The onboard analog temperature sensor, on ADC channel A2. These output a voltage
+/// linear with temperature: `V = offset + slope * T`.
+pub enum TempSensorModel {
+    /// TI LM61: 600mV at 0°C, 10mV/°C. Used on AnyLeaf boards.
+    Lm61,
+    /// Analog Devices TMP36: 500mV at 0°C, 10mV/°C.
+    Tmp36,
+    /// TI LM35: 0mV at 0°C, 10mV/°C.
+    Lm35,
+    /// Microchip MCP9700: 500mV at 0°C, 10mV/°C.
+    Mcp9700,
+    /// Microchip MCP9701: 400mV at 0°C, 19.5mV/°C.
+    Mcp9701,
+    /// A sensor with the given output at 0°C, in V, and slope, in V/°C.
+    Custom { offset: f32, slope: f32 },
+}
+
+impl TempSensorModel {
+    /// Output voltage at 0°C, in V.
+    pub fn offset(&self) -> f32 {
+        match self {
+            Self::Lm61 => 0.6,
+            Self::Tmp36 | Self::Mcp9700 => 0.5,
+            Self::Lm35 => 0.,
+            Self::Mcp9701 => 0.4,
+            Self::Custom { offset, .. } => *offset,
+        }
+    }
+
+    /// Output slope, in V/°C.
+    pub fn slope(&self) -> f32 {
+        match self {
+            Self::Mcp9701 => 0.019_5,
+            Self::Custom { slope, .. } => *slope,
+            _ => 0.01,
+        }
+    }
+
+    /// Map voltage to temperature, in °C.
+    pub fn temp(&self, V: f32) -> f32 {
+        match self {
+            Self::Lm61 => temp_from_voltage(V),
+            _ => (V - self.offset()) / self.slope(),
+        }
+    }
+}
+
+/// The pH, ORP or ISE module requesting a temperature reading. Gives temperature
+/// sources access to its onboard temperature sensor, its ADC, and the bus the ADC is
+/// read with; eg an I2C bus.
+pub struct Board<'a, A, BUS> {
+    adc: &'a mut A,
+    bus: &'a mut BUS,
+    adc_cal: AdcCal,
+    temp_sensor: TempSensorModel,
+    temp_cal_1: Option<CalPtT>,
+    temp_cal_2: Option<CalPtT>,
+}
+
+impl<'a, A, BUS> Board<'a, A, BUS> {
+    pub(crate) fn new(
+        adc: &'a mut A,
+        bus: &'a mut BUS,
+        adc_cal: AdcCal,
+        temp_sensor: TempSensorModel,
+        temp_cal_1: Option<CalPtT>,
+        temp_cal_2: Option<CalPtT>,
+    ) -> Self {
+        Self {
+            adc,
+            bus,
+            adc_cal,
+            temp_sensor,
+            temp_cal_1,
+            temp_cal_2,
+        }
+    }
+
+    /// The module's ADC.
+    pub fn adc(&mut self) -> &mut A {
+        self.adc
+    }
+
+    /// The bus the module's ADC is read with, for sources that share it.
+    pub fn bus(&mut self) -> &mut BUS {
+        self.bus
+    }
+}
+
+impl<'a, A, BUS> Board<'a, A, BUS>
+where
+    A: VoltageSource<BUS>,
+{
+    /// Read the voltage of the module's onboard temperature sensor. Corrected with the
+    /// ADC calibration.
+    pub fn read_onboard_temp_voltage(&mut self) -> Result<f32, A::Error> {
+        let V = self.adc.read_voltage(
+            AdcInput::Single(AdcChannel::A2),
+            FullScale::V2_048,
+            self.bus,
+        )?;
+
+        Ok(self.adc_cal.apply(V))
+    }
+
+    /// Read the module's onboard temperature sensor, in °C.
+    pub fn read_onboard_temp(&mut self) -> Result<f32, A::Error> {
+        let V = self.read_onboard_temp_voltage()?;
+        Ok(temp_from_voltage_cal(
+            V,
+            self.temp_sensor,
+            &self.temp_cal_1,
+            &self.temp_cal_2,
+        ))
+    }
+}
+
+/// A source of temperature readings, in °C.
+pub trait TemperatureSource<A, BUS> {
+    type Error;
+
+    /// Read temperature, in °C. `board` is the module requesting the reading.
+    fn read_temp(&mut self, board: &mut Board<A, BUS>) -> Result<f32, Self::Error>;
+}
+
+impl<A, BUS> TemperatureSource<A, BUS> for TempSource
+where
+    A: VoltageSource<BUS>,
+{
+    type Error = A::Error;
+
+    fn read_temp(&mut self, board: &mut Board<A, BUS>) -> Result<f32, Self::Error> {
+        match self {
+            TempSource::OnBoard => board.read_onboard_temp(),
+            TempSource::OffBoard(t_) => Ok(*t_),
+        }
+    }
+}
+
+impl<A, BUS, S> TemperatureSource<A, BUS> for &mut S
+where
+    S: TemperatureSource<A, BUS>,
+{
+    type Error = S::Error;
+
+    fn read_temp(&mut self, board: &mut Board<A, BUS>) -> Result<f32, Self::Error> {
+        (**self).read_temp(board)
+    }
+}
+
+/// Read temperature from a source, on behalf of `board`.
+pub(crate) fn read_source<A, BUS, S>(
+    t: &mut S,
+    mut board: Board<A, BUS>,
+) -> Result<f32, Error<A::Error, S::Error>>
+where
+    A: VoltageSource<BUS>,
+    S: TemperatureSource<A, BUS>,
+{
+    t.read_temp(&mut board).map_err(Error::Temp)
+}
+
+/// Measure a calibration point for `board`'s onboard temperature sensor, using
+/// `reference` for the true temperature.
+pub(crate) fn measure_cal_pt<A, BUS, S>(
+    reference: &mut S,
+    mut board: Board<A, BUS>,
+) -> Result<CalPtT, Error<A::Error, S::Error>>
+where
+    A: VoltageSource<BUS>,
+    S: TemperatureSource<A, BUS>,
+{
+    let T = reference.read_temp(&mut board).map_err(Error::Temp)?;
+    let V = board.read_onboard_temp_voltage().map_err(Error::Bus)?;
+
+    Ok(CalPtT::new(V, T))
+}
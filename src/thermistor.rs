@@ -0,0 +1,268 @@
+//! Support for NTC thermistors, read through a voltage divider on a spare ADS1115
+//! channel, eg A3 on the pH board. We convert resistance to temperature with the
+//! β model, or the Steinhart–Hart equation: `1/T = A + B*ln(R) + C*ln(R)^3`, where `T`
+//! is in Kelvin. Steinhart–Hart coefficients can be fit from 3 calibration points.
+
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+
+#[allow(unused_imports)]
+use num_traits::float::Float; // Required for `ln` and `powi` in `no_std`.
+
+use crate::{
+    AdcChannel, AdcError, AdcInput, AdcModel, Board, CalSlot, FullScale, I2cAdc, TemperatureSource,
+    ADC_ADDR_1, ADC_ADDR_2,
+};
+
+const KELVIN: f32 = 273.15;
+// Read with a 4.096V full scale range, so the divider's output isn't clipped.
+const THERMISTOR_RANGE: FullScale = FullScale::V4_096;
+
+#[derive(Clone, Copy, Debug)]
+/// Relates thermistor resistance to temperature.
+pub enum ThermistorModel {
+    /// The β model: `1/T = 1/T0 + ln(R/R0)/β`. `R0` is the resistance at `T0`, in Ohms,
+    /// and `T0` is in °C.
+    Beta { beta: f32, R0: f32, T0: f32 },
+    /// Steinhart–Hart coefficients, for `T` in Kelvin.
+    SteinhartHart { A: f32, B: f32, C: f32 },
+}
+
+impl ThermistorModel {
+    /// A common 10kΩ NTC, with β = 3950K.
+    pub const NTC_10K_3950: Self = Self::Beta {
+        beta: 3_950.,
+        R0: 10_000.,
+        T0: 25.,
+    };
+
+    /// Convert resistance in Ohms to temperature in °C.
+    pub fn temp(&self, R: f32) -> f32 {
+        let T_inv = match *self {
+            Self::Beta { beta, R0, T0 } => 1. / (T0 + KELVIN) + (R / R0).ln() / beta,
+            Self::SteinhartHart { A, B, C } => {
+                let ln_R = R.ln();
+                A + B * ln_R + C * ln_R.powi(3)
+            }
+        };
+
+        1. / T_inv - KELVIN
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// The voltage divider the thermistor is in.
+pub struct Divider {
+    /// Divider supply, in Volts.
+    pub supply: f32,
+    /// The fixed resistor, in Ohms.
+    pub series_resistance: f32,
+    /// `true` if the thermistor is between the ADC input and ground; `false` if it's
+    /// between the supply and the input.
+    pub thermistor_low: bool,
+}
+
+impl Divider {
+    pub fn new(supply: f32, series_resistance: f32, thermistor_low: bool) -> Self {
+        Self {
+            supply,
+            series_resistance,
+            thermistor_low,
+        }
+    }
+
+    /// Convert the divider's output voltage to thermistor resistance, in Ohms.
+    pub fn resistance(&self, V: f32) -> f32 {
+        let Rs = self.series_resistance;
+
+        if self.thermistor_low {
+            Rs * V / (self.supply - V)
+        } else {
+            Rs * (self.supply - V) / V
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// Data for a single thermistor calibration point.
+pub struct CalPtThermistor {
+    pub R: f32, // in Ohms
+    pub T: f32, // in Celsius
+}
+
+impl CalPtThermistor {
+    pub fn new(R: f32, T: f32) -> Self {
+        Self { R, T }
+    }
+}
+
+/// An NTC thermistor, read with an ADS1115. Can be used as a temperature source, eg
+/// for pH compensation, with the I2C bus of the sensor requesting it.
+pub struct Thermistor {
+    pub adc: I2cAdc,
+    pub channel: AdcChannel,
+    pub divider: Divider,
+    pub model: ThermistorModel,
+    nominal_model: ThermistorModel, // Restored by `reset_calibration`.
+    pub cal_1: Option<CalPtThermistor>,
+    pub cal_2: Option<CalPtThermistor>,
+    pub cal_3: Option<CalPtThermistor>,
+}
+
+impl Thermistor {
+    /// Create a thermistor on the pH or ORP board's ADC, on the low side of a divider
+    /// with a 10kΩ resistor, powered from 3.3V.
+    pub fn new(channel: AdcChannel, model: ThermistorModel) -> Self {
+        Self {
+            adc: I2cAdc::new(AdcModel::Ads1115, ADC_ADDR_1),
+            channel,
+            divider: Divider::new(3.3, 10_000., true),
+            model,
+            nominal_model: model,
+            cal_1: None,
+            cal_2: None,
+            cal_3: None,
+        }
+    }
+
+    /// Create a new thermistor with an ADC I2C address of 0x49.
+    pub fn new_alt_addr(channel: AdcChannel, model: ThermistorModel) -> Self {
+        Self {
+            adc: I2cAdc::new(AdcModel::Ads1115, ADC_ADDR_2),
+            ..Self::new(channel, model)
+        }
+    }
+
+    /// Measure the divider's output voltage.
+    pub fn read_voltage<I2C, E>(&mut self, i2c: &mut I2C) -> Result<f32, AdcError<E>>
+    where
+        I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+    {
+        self.adc
+            .read_voltage(AdcInput::Single(self.channel), THERMISTOR_RANGE, i2c)
+    }
+
+    /// Measure thermistor resistance, in Ohms.
+    pub fn read_resistance<I2C, E>(&mut self, i2c: &mut I2C) -> Result<f32, AdcError<E>>
+    where
+        I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+    {
+        let V = self.read_voltage(i2c)?;
+        Ok(self.divider.resistance(V))
+    }
+
+    /// Measure temperature, in °C.
+    pub fn read<I2C, E>(&mut self, i2c: &mut I2C) -> Result<f32, AdcError<E>>
+    where
+        I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+    {
+        let R = self.read_resistance(i2c)?;
+        Ok(self.model.temp(R))
+    }
+
+    /// Calibrate by measuring the thermistor at a known temperature `T`, in °C. Once
+    /// all 3 slots are filled, we fit Steinhart–Hart coefficients, and use them in
+    /// place of the nominal model. Points should span the range of use; eg 0°C, 25°C
+    /// and 50°C. Returns the point measured.
+    pub fn calibrate<I2C, E>(
+        &mut self,
+        slot: CalSlot,
+        T: f32,
+        i2c: &mut I2C,
+    ) -> Result<CalPtThermistor, AdcError<E>>
+    where
+        I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+    {
+        let pt = CalPtThermistor::new(self.read_resistance(i2c)?, T);
+
+        match slot {
+            CalSlot::One => self.cal_1 = Some(pt),
+            CalSlot::Two => self.cal_2 = Some(pt),
+            CalSlot::Three => self.cal_3 = Some(pt),
+        }
+
+        if let (Some(c0), Some(c1), Some(c2)) = (self.cal_1, self.cal_2, self.cal_3) {
+            self.calibrate_all(c0, c1, c2);
+        }
+
+        Ok(pt)
+    }
+
+    /// Fit Steinhart–Hart coefficients from 3 previously-measured points.
+    pub fn calibrate_all(
+        &mut self,
+        pt0: CalPtThermistor,
+        pt1: CalPtThermistor,
+        pt2: CalPtThermistor,
+    ) {
+        self.cal_1 = Some(pt0);
+        self.cal_2 = Some(pt1);
+        self.cal_3 = Some(pt2);
+
+        self.model = fit_steinhart_hart(&pt0, &pt1, &pt2);
+    }
+
+    /// Clear calibration points, and restore the nominal model.
+    pub fn reset_calibration(&mut self) {
+        self.model = self.nominal_model;
+        self.cal_1 = None;
+        self.cal_2 = None;
+        self.cal_3 = None;
+    }
+}
+
+impl<A, I2C, E> TemperatureSource<A, I2C> for Thermistor
+where
+    I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+{
+    type Error = AdcError<E>;
+
+    fn read_temp(&mut self, board: &mut Board<A, I2C>) -> Result<f32, Self::Error> {
+        self.read(board.bus())
+    }
+}
+
+/// Solve for Steinhart–Hart coefficients passing through 3 points. We compute in
+/// `f64`, since `C` is small, and sensitive to rounding.
+fn fit_steinhart_hart(
+    pt0: &CalPtThermistor,
+    pt1: &CalPtThermistor,
+    pt2: &CalPtThermistor,
+) -> ThermistorModel {
+    let L = [pt0.R as f64, pt1.R as f64, pt2.R as f64].map(f64::ln);
+    let Y = [pt0.T, pt1.T, pt2.T].map(|T| 1. / (T as f64 + KELVIN as f64));
+
+    let g1 = (Y[1] - Y[0]) / (L[1] - L[0]);
+    let g2 = (Y[2] - Y[0]) / (L[2] - L[0]);
+
+    let C = (g2 - g1) / (L[2] - L[1]) / (L[0] + L[1] + L[2]);
+    let B = g1 - C * (L[0].powi(2) + L[0] * L[1] + L[1].powi(2));
+    let A = Y[0] - (B + C * L[0].powi(2)) * L[0];
+
+    ThermistorModel::SteinhartHart {
+        A: A as f32,
+        B: B as f32,
+        C: C as f32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steinhart_hart_fit_reproduces_points() {
+        // A typical 10kΩ NTC, from 0 to 50°C.
+        let pts = [
+            CalPtThermistor::new(32_650., 0.),
+            CalPtThermistor::new(10_000., 25.),
+            CalPtThermistor::new(3_603., 50.),
+        ];
+
+        let model = fit_steinhart_hart(&pts[0], &pts[1], &pts[2]);
+
+        for pt in &pts {
+            let T = model.temp(pt.R);
+            assert!((T - pt.T).abs() < 0.01, "{}°C: {}°C", pt.T, T);
+        }
+    }
+}